@@ -1,36 +1,38 @@
-use reqwest::blocking::Client;
-use reqwest::header::{HeaderMap, AUTHORIZATION, CONTENT_TYPE};
-use serde::Serialize;
-use std::time::Duration;
-use crate::{get_model, AssistantMessage, SystemMessage};
+use reqwest::blocking::{Client, Response};
+use reqwest::StatusCode;
+use reqwest::header::{HeaderMap, CONTENT_TYPE};
+use serde_json::Value;
+use std::io::BufRead;
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+use crate::{AssistantMessage, SystemMessage};
 use crate::app::{ChatMessage, Model};
+use crate::config_manager::{get_config, Provider, ProviderKind, RoleConfig};
+use crate::provider::{ChatParams, StreamEvent, ThinkSplitter, Usage};
+use crate::redact::redact;
 
-#[derive(Serialize)]
-struct ChatRequest {
-    model: String,
-    messages: Vec<ChatMessage>,
-    stream: bool, // 虽然是同步线程，我们依然可以用流式处理
+/// 单个角色的请求目标
+struct Endpoint {
+    kind: ProviderKind,
+    api_base: String,
+    // 密钥在首次请求时解析
+    provider: Provider,
+    role: RoleConfig,
+    context_window: usize,
 }
 
 pub struct ApiClient {
     client: Client,
-    api_key: String,
-    api_base: String,
-    melchior_model: String,
-    casper_model: String,
-    balthazar_model: String
+    // 按 Model::ALL 顺序
+    endpoints: Vec<Endpoint>,
 }
 
 impl ApiClient {
     pub fn new() -> Result<Self, String> {
-        let config = crate::Config::load_or_init()?;
+        let config = get_config().read().unwrap();
 
         let mut headers = HeaderMap::new();
         headers.insert(CONTENT_TYPE, "application/json".parse().expect("无法添加 JSON Header"));
-        headers.insert(
-            AUTHORIZATION,
-            format!("Bearer {}", config.api_key).parse().expect("无法解析 API 校验 Header"),
-        );
 
         let client = Client::builder()
             .default_headers(headers)
@@ -38,71 +40,227 @@ impl ApiClient {
             .build()
             .expect("无法创建 Client");
 
-        Ok(Self {
-            client,
-            api_key: config.api_key.clone(),
-            api_base: config.api_base.clone(),
-            melchior_model: config.melchior_model.clone(),
-            casper_model: config.casper_model.clone(),
-            balthazar_model: config.balthazar_model.clone()
-        })
+        let endpoints = Model::ALL.iter().map(|model| {
+            let provider = config.provider(model);
+            Endpoint {
+                kind: provider.kind,
+                api_base: provider.api_base.clone(),
+                provider: provider.clone(),
+                role: config.role(model).clone(),
+                context_window: config.context_window(model),
+            }
+        }).collect();
+
+        Ok(Self { client, endpoints })
     }
 
-    pub fn send_chat_stream(&self, messages: Vec<ChatMessage>, tx: std::sync::mpsc::Sender<crate::AppMessage>) {
-        let url = format!("{}", self.api_base);
+    /// 流式请求角色的模型，返回服务端上报的 token 用量
+    pub fn send_chat_stream(&self, role: Model, messages: Vec<ChatMessage>, tx: std::sync::mpsc::Sender<crate::AppMessage>) -> Usage {
+        let endpoint = &self.endpoints[role.index()];
 
-        let model = get_model().read().unwrap().clone();
-        let model = match model {
-            Model::MELCHIOR => self.melchior_model.clone(),
-            Model::CASPER_I | Model::CASPER_II => self.casper_model.clone(),
-            Model::BALTHAZAR => self.balthazar_model.clone()
+        let _ = tx.send(crate::AppMessage::AIMsg(AssistantMessage::StreamStart(role)));
+
+        let usage = std::cell::Cell::new(Usage::default());
+
+        // 通过通道传回主线程
+        let forward = |event: StreamEvent| match event {
+            StreamEvent::Text(chunk) => {
+                let _ = tx.send(crate::AppMessage::AIMsg(AssistantMessage::ModelChunk(role, chunk)));
+            }
+            StreamEvent::Reasoning(chunk) => {
+                let _ = tx.send(crate::AppMessage::AIMsg(AssistantMessage::ReasoningChunk(role, chunk)));
+            }
+            StreamEvent::Usage(reported) => {
+                let mut merged = usage.get();
+                merged.merge(reported);
+                usage.set(merged);
+            }
+            StreamEvent::Error(_) | StreamEvent::Done => {}
         };
 
-        let request_body = ChatRequest {
-            model,
+        // 正文中的 <think> 标签拆分为思考过程
+        let mut splitter = ThinkSplitter::new();
+        let result = self.request(endpoint, messages, None).and_then(|response| {
+            // 关键点：逐行读取流式响应，由适配器解析为事件
+            for line in std::io::BufReader::new(response).lines() {
+                let line = line.map_err(|e| format!("读取流式响应失败: {}", e))?;
+                for event in endpoint.kind.adapter().parse_line(&line) {
+                    match event {
+                        StreamEvent::Text(chunk) => splitter.push(&chunk).into_iter().for_each(forward),
+                        StreamEvent::Error(e) => return Err(format!("服务端错误: {}", e)),
+                        StreamEvent::Done => return Ok(()),
+                        event => forward(event),
+                    }
+                }
+            }
+            Ok(())
+        });
+        splitter.finish().into_iter().for_each(forward);
+
+        if let Err(e) = result {
+            let _ = tx.send(crate::AppMessage::SysMsg(SystemMessage::SystemLog(redact(&e))));
+        }
+        let _ = tx.send(crate::AppMessage::AIMsg(AssistantMessage::TaskComplete(role)));
+
+        let usage = usage.get();
+        info!("{} token 用量: 输入 {:?} 输出 {:?}", role.name(), usage.prompt_tokens, usage.completion_tokens);
+        usage
+    }
+
+    /// 请求角色的模型并返回完整回复正文（不含思考过程），用于上下文摘要等内部任务
+    pub fn complete(&self, role: Model, messages: Vec<ChatMessage>) -> Result<String, String> {
+        let endpoint = &self.endpoints[role.index()];
+        let response = self.request(endpoint, messages, None)?;
+
+        let mut splitter = ThinkSplitter::new();
+        let mut reply = String::new();
+        let mut collect = |events: Vec<StreamEvent>| {
+            for event in events {
+                if let StreamEvent::Text(text) = event {
+                    reply.push_str(&text);
+                }
+            }
+        };
+
+        'read: for line in std::io::BufReader::new(response).lines() {
+            let line = line.map_err(|e| format!("读取流式响应失败: {}", e))?;
+            for event in endpoint.kind.adapter().parse_line(&line) {
+                match event {
+                    StreamEvent::Text(chunk) => collect(splitter.push(&chunk)),
+                    StreamEvent::Error(e) => return Err(redact(&format!("服务端错误: {}", e))),
+                    StreamEvent::Done => break 'read,
+                    _ => {}
+                }
+            }
+        }
+        collect(splitter.finish());
+        Ok(reply)
+    }
+
+    /// 按角色的接口格式与采样参数发起流式请求；max_tokens 非空时覆盖配置值
+    fn request(&self, endpoint: &Endpoint, messages: Vec<ChatMessage>, max_tokens: Option<u32>) -> Result<Response, String> {
+        let adapter = endpoint.kind.adapter();
+        let mut body = adapter.body(ChatParams {
+            role: &endpoint.role,
             messages,
-            stream: true,
+            max_tokens,
+            context_window: endpoint.context_window,
+            stream_usage: endpoint.provider.stream_usage(),
+        });
+
+        let api_key = endpoint.provider.key()?;
+        let send = |body: &Value| {
+            let request = self.client.post(&endpoint.api_base).json(body);
+            adapter.auth(request, &api_key).send()
+                .map_err(|e| redact(&format!("网络请求失败: {}", e.without_url())))
         };
+        let mut response = send(&body)?;
+
+        // 部分 OpenAI 兼容服务不认识 stream_options 而返回 400：去掉后重试一次，成功则记住
+        if response.status() == StatusCode::BAD_REQUEST
+            && let Some(fields) = body.as_object_mut()
+            && fields.remove("stream_options").is_some()
+        {
+            let retry = send(&body)?;
+            if retry.status().is_success() {
+                warn!("provider <{}> 不支持 stream_options，已停止请求用量统计", endpoint.provider.name);
+                endpoint.provider.reject_stream_usage();
+            }
+            response = retry;
+        }
 
-        let response = self.client.post(url)
-            .header("Authorization", format!("Bearer {}", self.api_key))
-            .header("Content-Type", "application/json")
-            .json(&request_body)
-            .send();
-
-        match response {
-            Ok(res) => {
-                // 关键点：使用读取器处理 SSE 流
-                let reader = std::io::BufReader::new(res);
-                use std::io::BufRead;
-
-                for line in reader.lines() {
-                    let line = line.unwrap_or_default();
-                    if line.starts_with("data: ") {
-                        let data = &line[6..];
-                        if data == "[DONE]" { break; }
-
-                        // 解析 JSON 提取文本片段 (Chunk)
-                        if let Ok(json) = serde_json::from_str::<serde_json::Value>(data) {
-                            if let Some(content) = json["choices"][0]["delta"]["content"].as_str() {
-                                // 通过通道传回主线程
-                                let _ = tx.send(crate::AppMessage::AIMsg(AssistantMessage::ModelChunk(content.to_string())));
-                            }
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().unwrap_or_default();
+            let body: String = body.trim().chars().take(200).collect();
+            return Err(redact(&format!("服务端返回 {}: {}", status, body)))
+        }
+        Ok(response)
+    }
+
+    /*
+     * -------- [ 流式探测 ] --------
+     * 以极短的提示词请求角色的模型，测量首 token 时间并检查流式输出是否正常
+     */
+    pub fn probe(&self, role: Model) -> Result<StreamProbe, String> {
+        let endpoint = &self.endpoints[role.index()];
+        let messages = vec![ChatMessage { role: "user".into(), content: PROBE_PROMPT.into() }];
+
+        let start = Instant::now();
+        let response = self.request(endpoint, messages, Some(PROBE_MAX_TOKENS))?;
+
+        let mut probe = StreamProbe { first_token: None, total: Duration::ZERO, events: 0, done: false };
+        'read: for line in std::io::BufReader::new(response).lines() {
+            let line = line.map_err(|e| format!("读取流式响应失败: {}", e))?;
+            for event in endpoint.kind.adapter().parse_line(&line) {
+                match event {
+                    StreamEvent::Text(chunk) | StreamEvent::Reasoning(chunk) => {
+                        if probe.first_token.is_none() && !chunk.is_empty() {
+                            probe.first_token = Some(start.elapsed());
                         }
                     }
+                    StreamEvent::Usage(_) => {}
+                    StreamEvent::Error(e) => return Err(redact(&format!("服务端错误: {}", e))),
+                    StreamEvent::Done => {
+                        probe.done = true;
+                        break 'read
+                    }
                 }
-            }
-            Err(e) => {
-                let error_msg = format!("网络请求失败: {}", e);
-                // 简单过滤潜在的敏感信息
-                let safe_msg = if error_msg.contains("Bearer ") || error_msg.contains("api_key") {
-                    "网络请求失败: 认证错误或网络问题".to_string()
-                } else {
-                    error_msg
-                };
-                let _ = tx.send(crate::AppMessage::SysMsg(SystemMessage::SystemLog(safe_msg)));
+                probe.events += 1;
             }
         }
-        let _ = tx.send(crate::AppMessage::AIMsg(AssistantMessage::TaskComplete));
+        probe.total = start.elapsed();
+        Ok(probe)
+    }
+}
+
+// 探测用的提示词与输出上限，尽量减少耗时与费用
+const PROBE_PROMPT: &str = "Reply with: ok";
+const PROBE_MAX_TOKENS: u32 = 8;
+
+/// 流式探测结果
+pub struct StreamProbe {
+    // 收到第一个非空文本或思考片段的时间
+    pub first_token: Option<Duration>,
+    pub total: Duration,
+    // 收到的流式事件数（不含结束标记）
+    pub events: usize,
+    // 是否收到结束标记
+    pub done: bool,
+}
+
+/*
+ * -------- [ 模型列表 ] --------
+ * OpenAI 兼容接口与 Anthropic 读取 /v1/models 的 data[].id，Ollama 读取 /api/tags 的 models[].name
+ */
+pub fn list_models(kind: ProviderKind, root: &str, api_key: &str) -> Result<Vec<String>, String> {
+    let client = Client::builder()
+        .timeout(Duration::from_secs(10))
+        .build()
+        .expect("无法创建 Client");
+
+    let url = kind.models_url(root);
+    let request = kind.adapter().auth(client.get(&url), api_key);
+
+    let response = request.send()
+        .map_err(|e| redact(&format!("无法连接 <{}>: {}", url, e.without_url())))?;
+    let status = response.status();
+    if !status.is_success() {
+        return Err(format!("<{}> 返回 {}", url, status))
     }
-}
\ No newline at end of file
+
+    let json: serde_json::Value = response.json()
+        .map_err(|e| format!("<{}> 返回内容不是有效的 JSON: {}", url, e))?;
+    let (list, field) = match kind {
+        ProviderKind::OpenAI | ProviderKind::Anthropic => (&json["data"], "id"),
+        ProviderKind::Ollama | ProviderKind::OllamaNative => (&json["models"], "name"),
+    };
+
+    let mut models: Vec<String> = list.as_array()
+        .ok_or(format!("<{}> 返回内容缺少模型列表", url))?
+        .iter()
+        .filter_map(|m| m[field].as_str().map(String::from))
+        .collect();
+    models.sort();
+    Ok(models)
+}
@@ -1,40 +1,103 @@
 use std::io::Stdout;
 use std::sync::{Arc, OnceLock, RwLock};
+use std::time::Duration;
 use ratatui::backend::CrosstermBackend;
 use ratatui::Terminal;
 use serde::{Deserialize, Serialize};
+use crate::pins::Pin;
+use crate::usage::UsageTotals;
 
+// 对话与工具调用相关消息均带有所属角色，切换标签页后仍能路由到正确的上下文
 pub enum AppMessage {
-    UserQuery(String),
+    UserQuery(Model, String),
     AIMsg(AssistantMessage),
-    SysMsg(SystemMessage)
+    SysMsg(SystemMessage),
+    CtrlMsg(ControlMessage)
 }
 
 pub enum AssistantMessage {
-    ModelChunk(String),
-    AssistantReply(String),
-    TaskComplete,
+    StreamStart(Model),
+    ModelChunk(Model, String),
+    // 思考过程片段，单独显示，不写入回复正文
+    ReasoningChunk(Model, String),
+    AssistantReply(Model, String),
+    TaskComplete(Model),
 }
 
 pub enum SystemMessage {
     // 命令执行
-    ExecCommand(String),
-    ExecResult(String),
-    // 读取文件
-    Read(String),
-    ReadResult(String),
+    ExecCommand(Model, String),
+    ExecResult(Model, ToolOutput),
+    // 读取文件（结果带文件路径，用于补丁后标记过期的读取结果）
+    Read(Model, String),
+    ReadResult(Model, String, ToolOutput),
     // 应用补丁
-    Diff(String, String),
-    DiffResult(String),
+    Diff(Model, String, String),
+    DiffResult(Model, String, ToolOutput),
+    // 用户拒绝工具调用
+    Rejected(Model, String),
     // 系统日志
     SystemLog(String),
+    // 提示信息（命令执行结果等）
+    Notice(String),
+    // 各角色上下文的估算 token 数（按 Model::ALL 顺序）
+    ContextTokens([usize; 4]),
+    // 本次会话的累计用量
+    UsageTotals(UsageTotals),
+    // 上下文压缩的进度与结果，显示在对应角色的标签页
+    Compacted(Model, String),
+}
+
+/*
+ * -------- [ 工具调用结果 ] --------
+ * content 即写入模型上下文的完整文本（含截断标记），UI 展示同一份内容
+ */
+#[derive(Clone)]
+pub struct ToolOutput {
+    pub success: bool,
+    // 退出状态摘要，如 `exit 0`、`signal 9`、`已应用`
+    pub status: String,
+    pub elapsed: Duration,
+    pub content: String,
+    // 被截断时为原始行数
+    pub truncated: Option<usize>,
+}
+
+/// 斜杠命令发往 IO 线程的控制消息
+pub enum ControlMessage {
+    ClearHistory(Model),
+    ShowHistory(Model),
+    SaveSession(String),
+    LoadSession(String),
+    ReloadConfig,
+    Doctor,
+    ShowUsage,
+    Undo(Model),
+    // 固定上下文；Unpin 的序号从 1 开始，None 表示全部
+    Pin(Model, Pin),
+    ShowPins(Model),
+    Unpin(Model, Option<usize>),
 }
 
 #[derive(Clone)]
 pub enum PendingAction {
     None,
     ConfirmExec(String),
-    ConfirmDiff(String, String)
+    ConfirmDiff(String, String),
+    // 拒绝时填写理由：被拒绝的操作 + 用户输入的理由
+    RejectReason(Box<PendingAction>, String)
+}
+
+impl PendingAction {
+    /// 用于反馈给模型的操作描述，如 exec `ls -la`
+    pub fn describe(&self) -> String {
+        match self {
+            PendingAction::ConfirmExec(cmd) => format!("exec `{}`", cmd.trim()),
+            PendingAction::ConfirmDiff(file_path, _) => format!("diff `{}`", file_path),
+            PendingAction::RejectReason(action, _) => action.describe(),
+            PendingAction::None => String::new()
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Clone)]
@@ -43,8 +106,8 @@ pub struct ChatMessage {
     pub content: String,
 }
 
-#[allow(dead_code, non_camel_case_types)]
-#[derive(Clone)]
+#[allow(dead_code, non_camel_case_types, clippy::upper_case_acronyms)]
+#[derive(Clone, Copy, PartialEq, Eq)]
 pub enum Model {
     MELCHIOR,
     CASPER_I,
@@ -52,6 +115,29 @@ pub enum Model {
     BALTHAZAR
 }
 
+impl Model {
+    pub const ALL: [Model; 4] = [Model::MELCHIOR, Model::CASPER_I, Model::CASPER_II, Model::BALTHAZAR];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Model::MELCHIOR => "MELCHIOR",
+            Model::CASPER_I => "CASPER-I",
+            Model::CASPER_II => "CASPER-II",
+            Model::BALTHAZAR => "BALTHAZAR"
+        }
+    }
+
+    /// 在 Model::ALL 中的下标，用于按角色索引的状态数组
+    pub fn index(&self) -> usize {
+        *self as usize
+    }
+
+    /// 从名称解析（不区分大小写，如 casper-i）
+    pub fn parse(name: &str) -> Option<Model> {
+        Model::ALL.into_iter().find(|m| m.name().eq_ignore_ascii_case(name))
+    }
+}
+
 #[allow(dead_code)]
 pub enum Tool {
     Exec,
@@ -0,0 +1,111 @@
+use std::path::PathBuf;
+use clap::{Args, Parser, Subcommand};
+use crate::app::Model;
+use crate::config_layers::{parse_assignment, Overrides};
+
+// ask 与 run 的退出码说明，与 headless 中的常量对应
+const HEADLESS_EXIT_CODES: &str = "退出码:
+  0  完成
+  1  请求失败或超过轮数上限
+  2  有工具调用被 tool_policy 拒绝
+  3  有补丁应用失败或命令以非零状态退出（优先于 2）";
+
+/// Oxicodent —— 基于 M.A.G.I. 上下文分离架构的开发助手
+#[derive(Parser)]
+#[command(version, about)]
+pub struct Cli {
+    #[command(flatten)]
+    pub overrides: ConfigArgs,
+
+    #[command(subcommand)]
+    pub command: Option<CliCommand>,
+}
+
+/// 配置覆盖参数，优先级高于配置文件与环境变量
+#[derive(Args)]
+pub struct ConfigArgs {
+    /// --api-base 作用的 provider（默认为 MELCHIOR 所用的 provider）
+    #[arg(long, global = true)]
+    pub provider: Option<String>,
+
+    /// 覆盖 provider 的 api_base
+    #[arg(long, global = true)]
+    pub api_base: Option<String>,
+
+    /// 代码高亮主题
+    #[arg(long, global = true)]
+    pub theme: Option<String>,
+
+    /// 模型上下文窗口大小（token）
+    #[arg(long, global = true)]
+    pub context_window: Option<usize>,
+
+    /// 覆盖任意配置项，如 --set roles.melchior.model=qwen3:14b
+    #[arg(long = "set", value_name = "KEY=VALUE", global = true)]
+    pub assignments: Vec<String>,
+}
+
+#[derive(Subcommand)]
+pub enum CliCommand {
+    /// 配置相关操作
+    Config {
+        #[command(subcommand)]
+        action: ConfigAction,
+    },
+    /// 检查各 provider 的连接、模型名与流式输出
+    Doctor,
+    /// 非交互提问，回复输出到标准输出
+    #[command(after_help = HEADLESS_EXIT_CODES)]
+    Ask {
+        /// 回答问题的角色
+        #[arg(long, default_value = "melchior", value_parser = parse_role)]
+        role: Model,
+        /// 自动执行全部工具调用（忽略 tool_policy）
+        #[arg(long, short)]
+        yes: bool,
+        /// 问题；省略时从标准输入读取
+        question: Option<String>,
+    },
+    /// 按规格书非交互地完成修改
+    #[command(after_help = HEADLESS_EXIT_CODES)]
+    Run {
+        /// 规格书文件
+        #[arg(long, value_name = "FILE")]
+        spec: PathBuf,
+        /// 执行规格书的角色
+        #[arg(long, default_value = "casper-ii", value_parser = parse_role)]
+        role: Model,
+        /// 自动执行全部工具调用（忽略 tool_policy）
+        #[arg(long, short)]
+        yes: bool,
+    },
+}
+
+fn parse_role(name: &str) -> Result<Model, String> {
+    Model::parse(name).ok_or(format!("未知角色: {}（可选 melchior、casper-i、casper-ii、balthazar）", name))
+}
+
+#[derive(Subcommand)]
+pub enum ConfigAction {
+    /// 显示合并后的配置及每项的来源（密钥打码）
+    Show,
+    /// 将旧版扁平格式的用户配置写回为 providers 格式（原文件备份为 config.json.bak）
+    Migrate,
+}
+
+impl ConfigArgs {
+    pub fn into_overrides(self) -> Result<Overrides, String> {
+        let assignments = self.assignments.iter()
+            .map(|a| parse_assignment(a))
+            .collect::<Result<_, _>>()?;
+
+        Ok(Overrides {
+            provider: self.provider,
+            api_base: self.api_base,
+            theme: self.theme,
+            context_window: self.context_window,
+            assignments,
+            ..Overrides::default()
+        })
+    }
+}
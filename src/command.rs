@@ -0,0 +1,329 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use chrono::Local;
+use crate::app::{AppMessage, ControlMessage, Model};
+use crate::config_manager::{get_config, get_workspace_path};
+use crate::io_thread::IOThread;
+use crate::pins::Pin;
+use crate::ui::Ui;
+
+/// 命令执行时可访问的状态
+pub struct CommandContext<'a> {
+    pub ui: &'a mut Ui,
+    pub io_thread: &'a mut IOThread,
+    // 命令名之后的原文，保留空白与换行（如 /pin 的文本）
+    pub raw_args: &'a str,
+}
+
+/*
+ * -------- [ 斜杠命令注册表 ] --------
+ * - 新增命令只需在 COMMANDS 中追加一项
+ * - args 为 Tab 补全时第一个参数的候选
+ * - run 返回 Err 时，错误信息与 usage 一同显示在对话区
+ */
+pub struct SlashCommand {
+    pub name: &'static str,
+    pub usage: &'static str,
+    pub description: &'static str,
+    pub args: &'static [&'static str],
+    pub run: fn(&[&str], &mut CommandContext) -> Result<(), String>,
+}
+
+const MODEL_ARGS: &[&str] = &["melchior", "casper-i", "casper-ii", "balthazar"];
+
+pub const COMMANDS: &[SlashCommand] = &[
+    SlashCommand {
+        name: "model",
+        usage: "/model <melchior|casper-i|casper-ii|balthazar>",
+        description: "切换当前模型（同 F1-F4）",
+        args: MODEL_ARGS,
+        run: cmd_model,
+    },
+    SlashCommand {
+        name: "clear",
+        usage: "/clear [model]",
+        description: "清空指定模型（默认当前模型）的上下文，仅保留系统提示词",
+        args: MODEL_ARGS,
+        run: cmd_clear,
+    },
+    SlashCommand {
+        name: "history",
+        usage: "/history",
+        description: "列出当前模型的上下文消息",
+        args: &[],
+        run: cmd_history,
+    },
+    SlashCommand {
+        name: "save",
+        usage: "/save [name]",
+        description: "保存会话至 .oxicodent/sessions/<name>.json",
+        args: &[],
+        run: cmd_save,
+    },
+    SlashCommand {
+        name: "load",
+        usage: "/load [name]",
+        description: "从 .oxicodent/sessions/<name>.json 载入会话",
+        args: &[],
+        run: cmd_load,
+    },
+    SlashCommand {
+        name: "config",
+        usage: "/config <reload|show>",
+        description: "重新读取配置文件 / 显示合并后的配置及来源",
+        args: &["reload", "show"],
+        run: cmd_config,
+    },
+    SlashCommand {
+        name: "doctor",
+        usage: "/doctor",
+        description: "检查各 provider 的连接、模型名与流式输出",
+        args: &[],
+        run: cmd_doctor,
+    },
+    SlashCommand {
+        name: "usage",
+        usage: "/usage",
+        description: "显示本次会话各角色、各模型的 token 用量与费用",
+        args: &[],
+        run: cmd_usage,
+    },
+    SlashCommand {
+        name: "undo",
+        usage: "/undo",
+        description: "撤销当前模型的上一轮对话",
+        args: &[],
+        run: cmd_undo,
+    },
+    SlashCommand {
+        name: "pin",
+        usage: "/pin <file|text>",
+        description: "为当前模型固定文件（每次请求前重新读取）或文本，不受压缩影响",
+        args: &[],
+        run: cmd_pin,
+    },
+    SlashCommand {
+        name: "pins",
+        usage: "/pins",
+        description: "列出当前模型的固定上下文",
+        args: &[],
+        run: cmd_pins,
+    },
+    SlashCommand {
+        name: "unpin",
+        usage: "/unpin <n|all>",
+        description: "移除当前模型第 n 项（见 /pins）或全部固定上下文",
+        args: &["all"],
+        run: cmd_unpin,
+    },
+    SlashCommand {
+        name: "copy",
+        usage: "/copy [n]",
+        description: "复制倒数第 n 条（默认最后一条）模型回复到剪贴板",
+        args: &[],
+        run: cmd_copy,
+    },
+    SlashCommand {
+        name: "export",
+        usage: "/export [path]",
+        description: "导出对话记录为 Markdown（默认 .oxicodent/transcripts/）",
+        args: &[],
+        run: cmd_export,
+    },
+    SlashCommand {
+        name: "help",
+        usage: "/help",
+        description: "显示可用命令",
+        args: &[],
+        run: cmd_help,
+    },
+];
+
+const DEFAULT_SESSION: &str = "default";
+
+pub fn find_command(name: &str) -> Option<&'static SlashCommand> {
+    COMMANDS.iter().find(|c| c.name == name)
+}
+
+/*
+ * -------- [ Tab 补全 ] --------
+ * 返回补全后的输入；有多个候选时补全到公共前缀，并返回候选列表
+ */
+pub fn complete(input: &str) -> (String, Vec<String>) {
+    let Some(body) = input.strip_prefix('/') else { return (input.to_string(), Vec::new()) };
+
+    let (prefix, partial, candidates): (String, &str, Vec<&str>) = match body.split_once(' ') {
+        None => ("/".into(), body, COMMANDS.iter().map(|c| c.name).collect()),
+        Some((name, rest)) if !rest.contains(' ') => match find_command(name) {
+            Some(cmd) => (format!("/{} ", name), rest, cmd.args.to_vec()),
+            None => return (input.to_string(), Vec::new()),
+        },
+        _ => return (input.to_string(), Vec::new()),
+    };
+
+    let matches: Vec<&str> = candidates.into_iter()
+        .filter(|c| c.starts_with(partial))
+        .collect();
+
+    match matches.as_slice() {
+        [] => (input.to_string(), Vec::new()),
+        [only] => (format!("{}{} ", prefix, only), Vec::new()),
+        many => {
+            let common = many.iter().skip(1).fold(many[0].to_string(), |acc, m| {
+                acc.chars().zip(m.chars()).take_while(|(a, b)| a == b).map(|(a, _)| a).collect()
+            });
+            (format!("{}{}", prefix, common), many.iter().map(|m| m.to_string()).collect())
+        }
+    }
+}
+
+fn parse_model_arg(arg: &str) -> Result<Model, String> {
+    Model::parse(arg).ok_or_else(|| format!("未知模型: {}", arg))
+}
+
+fn cmd_model(args: &[&str], ctx: &mut CommandContext) -> Result<(), String> {
+    let [name] = args else { return Err("缺少模型名".into()) };
+    let model = parse_model_arg(name)?;
+    ctx.ui.switch_tab(model);
+    ctx.ui.push_notice(&format!("已切换至 {}", model.name()));
+    Ok(())
+}
+
+fn cmd_clear(args: &[&str], ctx: &mut CommandContext) -> Result<(), String> {
+    let model = match args {
+        [] => ctx.ui.tab().model,
+        [name] => parse_model_arg(name)?,
+        _ => return Err("参数过多".into()),
+    };
+    ctx.io_thread.send(AppMessage::CtrlMsg(ControlMessage::ClearHistory(model)));
+    Ok(())
+}
+
+fn cmd_history(_: &[&str], ctx: &mut CommandContext) -> Result<(), String> {
+    ctx.io_thread.send(AppMessage::CtrlMsg(ControlMessage::ShowHistory(ctx.ui.tab().model)));
+    Ok(())
+}
+
+fn session_name(args: &[&str]) -> Result<String, String> {
+    match args {
+        [] => Ok(DEFAULT_SESSION.into()),
+        [name] if !name.contains(['/', '\\']) && !name.starts_with('.') => Ok(name.to_string()),
+        [name] => Err(format!("非法会话名: {}", name)),
+        _ => Err("参数过多".into()),
+    }
+}
+
+fn cmd_save(args: &[&str], ctx: &mut CommandContext) -> Result<(), String> {
+    let name = session_name(args)?;
+    ctx.io_thread.send(AppMessage::CtrlMsg(ControlMessage::SaveSession(name)));
+    Ok(())
+}
+
+fn cmd_load(args: &[&str], ctx: &mut CommandContext) -> Result<(), String> {
+    let name = session_name(args)?;
+    ctx.io_thread.send(AppMessage::CtrlMsg(ControlMessage::LoadSession(name)));
+    Ok(())
+}
+
+fn cmd_config(args: &[&str], ctx: &mut CommandContext) -> Result<(), String> {
+    match args {
+        ["reload"] => {
+            ctx.io_thread.send(AppMessage::CtrlMsg(ControlMessage::ReloadConfig));
+            Ok(())
+        }
+        ["show"] => {
+            let description = get_config().read().unwrap().describe();
+            ctx.ui.push_notice(&format!("当前配置:\n{}", description.trim_end()));
+            Ok(())
+        }
+        _ => Err("未知子命令".into()),
+    }
+}
+
+fn cmd_doctor(_: &[&str], ctx: &mut CommandContext) -> Result<(), String> {
+    ctx.ui.push_notice("正在检查连接...");
+    ctx.io_thread.send(AppMessage::CtrlMsg(ControlMessage::Doctor));
+    Ok(())
+}
+
+fn cmd_usage(_: &[&str], ctx: &mut CommandContext) -> Result<(), String> {
+    ctx.io_thread.send(AppMessage::CtrlMsg(ControlMessage::ShowUsage));
+    Ok(())
+}
+
+fn cmd_undo(_: &[&str], ctx: &mut CommandContext) -> Result<(), String> {
+    ctx.io_thread.send(AppMessage::CtrlMsg(ControlMessage::Undo(ctx.ui.tab().model)));
+    Ok(())
+}
+
+/// 参数为已存在的文件路径时固定该文件，否则按原文固定整段文本
+fn cmd_pin(args: &[&str], ctx: &mut CommandContext) -> Result<(), String> {
+    if args.is_empty() {
+        return Err("缺少文件路径或文本".into())
+    }
+    let text = ctx.raw_args.to_string();
+    let pin = if Path::new(&text).is_file() { Pin::File(text) } else { Pin::Text(text) };
+    ctx.io_thread.send(AppMessage::CtrlMsg(ControlMessage::Pin(ctx.ui.tab().model, pin)));
+    Ok(())
+}
+
+fn cmd_pins(_: &[&str], ctx: &mut CommandContext) -> Result<(), String> {
+    ctx.io_thread.send(AppMessage::CtrlMsg(ControlMessage::ShowPins(ctx.ui.tab().model)));
+    Ok(())
+}
+
+fn cmd_unpin(args: &[&str], ctx: &mut CommandContext) -> Result<(), String> {
+    let index = match args {
+        ["all"] => None,
+        [n] => Some(n.parse::<usize>().map_err(|_| format!("非法序号: {}", n))?),
+        [] => return Err("缺少序号".into()),
+        _ => return Err("参数过多".into()),
+    };
+    ctx.io_thread.send(AppMessage::CtrlMsg(ControlMessage::Unpin(ctx.ui.tab().model, index)));
+    Ok(())
+}
+
+/// 通过 OSC 52 转义序列写入终端剪贴板（支持 SSH 等远程会话）
+fn cmd_copy(args: &[&str], ctx: &mut CommandContext) -> Result<(), String> {
+    let n = match args {
+        [] => 1,
+        [n] => n.parse::<usize>().map_err(|_| format!("非法序号: {}", n))?,
+        _ => return Err("参数过多".into()),
+    };
+    let reply = ctx.ui.tab().transcript.nth_last_reply(n)
+        .ok_or(format!("没有倒数第 {} 条模型回复", n))?
+        .to_string();
+
+    ctx.ui.copy_to_clipboard(&reply);
+    let chars = reply.chars().count();
+    ctx.ui.push_notice(&format!("已复制 {} 个字符", chars));
+    Ok(())
+}
+
+fn cmd_export(args: &[&str], ctx: &mut CommandContext) -> Result<(), String> {
+    let path = match args {
+        [] => {
+            let dir = get_workspace_path()?.join("transcripts");
+            fs::create_dir_all(&dir)
+                .map_err(|e| format!("无法创建目录 <{}>: {}", dir.to_string_lossy(), e))?;
+            dir.join(format!("{}.md", Local::now().format("%Y%m%d-%H%M%S")))
+        }
+        [path] => PathBuf::from(path),
+        _ => return Err("参数过多".into()),
+    };
+
+    fs::write(&path, ctx.ui.tab().transcript.to_markdown())
+        .map_err(|e| format!("无法写入文件 <{}>: {}", path.to_string_lossy(), e))?;
+    ctx.ui.push_notice(&format!("对话记录已导出至 <{}>", path.to_string_lossy()));
+    Ok(())
+}
+
+fn cmd_help(_: &[&str], ctx: &mut CommandContext) -> Result<(), String> {
+    let mut help = String::from("可用命令:");
+    for cmd in COMMANDS {
+        help.push_str(&format!("\n  {:<50} {}", cmd.usage, cmd.description));
+    }
+    help.push_str("\n未注册的 /开头输入（如文件路径）按普通问题发送；以 // 开头时去掉一个 / 后原样发送");
+    ctx.ui.push_notice(&help);
+    Ok(())
+}
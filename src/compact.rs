@@ -0,0 +1,364 @@
+use crate::api_client::ApiClient;
+use crate::app::{ChatMessage, Model};
+use crate::config_manager::get_config;
+use crate::tokenizer::{count_messages, count_tokens};
+
+// 上下文超过窗口的该比例时触发压缩，压缩到目标比例以下
+const TRIGGER_PERCENT: usize = 80;
+const TARGET_PERCENT: usize = 50;
+// 小于该值的工具输出保留原文
+const MIN_ELIDE_TOKENS: usize = 200;
+// 交给 BALTHAZAR 摘要时，单条消息最多保留的字符数
+const SUMMARY_MESSAGE_CHARS: usize = 2000;
+// 最近的若干条工具输出保留原文，即使属于当前轮次之前
+const KEEP_RECENT_TOOL_RESULTS: usize = 3;
+
+const ELIDED_PREFIX: &str = "[已省略过期的工具输出";
+const SUMMARY_PREFIX: &str = "[之前对话的摘要]";
+/*
+ * 规格书与提示词的耦合：CASPER_I_PROMPT.md 要求 CASPER_I 以行首 ```spec 开始的代码块输出最终修改方案，
+ * 压缩时据此识别并保留最近一份规格书，修改提示词中的格式时需同步此常量。
+ * 只认 assistant 消息：工具输出（如读取到含该标记的文件）与用户消息都不算；
+ * headless::run_spec 以用户消息发送规格书，作为最后一轮对话本身即被保留
+ */
+const SPEC_FENCE: &str = "```spec";
+
+const SUMMARY_PROMPT: &str = "你是上下文压缩器。请将下面的对话记录压缩为简洁的摘要，供助手继续工作：\n\
+- 保留用户的目标、已做出的决定与约束\n\
+- 保留涉及的文件路径、函数名、命令及其关键结果\n\
+- 保留尚未完成的事项\n\
+- 省略寒暄与重复内容，不要编造\n\
+直接输出摘要正文。";
+
+/// 角色的上下文预算（token）
+struct Budget {
+    trigger: usize,
+    target: usize,
+}
+
+impl Budget {
+    fn of(model: &Model) -> Self {
+        let window = get_config().read().unwrap().context_window(model);
+        Self { trigger: window * TRIGGER_PERCENT / 100, target: window * TARGET_PERCENT / 100 }
+    }
+}
+
+/*
+ * -------- [ 上下文压缩 ] --------
+ * 发送请求前检查估算 token 数，超过预算时依次：
+ * 1. 将大段工具输出替换为占位说明，最近的 KEEP_RECENT_TOOL_RESULTS 条与之前的摘要除外；
+ *    包括最后一轮中的工具输出，避免单轮连续调用工具（及 headless 模式）时无法压缩
+ * 2. 用 BALTHAZAR 将较早轮次摘要为一条消息
+ * 3. 摘要失败时丢弃最早的消息
+ * 始终保留开头的系统提示词、最近一个 ```spec 块与最后一轮对话的用户消息；
+ * reserved 为不在 messages 中、但会随请求发送的 token 数（固定上下文），从预算中扣除；
+ * 压缩过程通过 notify 告知用户
+ */
+pub fn compact(model: Model, messages: &mut Vec<ChatMessage>, reserved: usize, client: &ApiClient, notify: &dyn Fn(String)) {
+    let mut budget = Budget::of(&model);
+    budget.trigger = budget.trigger.saturating_sub(reserved);
+    budget.target = budget.target.saturating_sub(reserved);
+    shrink(model, messages, budget, &|older| summarize(client, older), notify)
+}
+
+/// 按预算依次执行压缩步骤；summarize 为第 2 步的摘要方式
+fn shrink(
+    model: Model,
+    messages: &mut Vec<ChatMessage>,
+    budget: Budget,
+    summarize: &dyn Fn(&[&ChatMessage]) -> Result<String, String>,
+    notify: &dyn Fn(String),
+) {
+    let before = count_messages(&model, messages);
+    if before <= budget.trigger {
+        return
+    }
+
+    // 系统提示词不参与压缩；之前的摘要会与较早的轮次一起重新摘要
+    let head = messages.iter()
+        .take_while(|m| m.role == "system" && !m.content.starts_with(SUMMARY_PREFIX))
+        .count();
+    let last_user = messages.iter().rposition(|m| m.role == "user").filter(|&i| i > head);
+    let spec = messages.iter().rposition(is_spec);
+    let mut total = before;
+    let mut actions = Vec::new();
+
+    // --- [ 1. 省略过期的工具输出 ] ---
+    let mut tool_results: Vec<usize> = (head..messages.len())
+        .filter(|&i| is_tool_result(&messages[i]))
+        .collect();
+    tool_results.truncate(tool_results.len().saturating_sub(KEEP_RECENT_TOOL_RESULTS));
+    let mut elided = 0;
+    for i in tool_results {
+        if total <= budget.target {
+            break
+        }
+        let msg = &mut messages[i];
+        let tokens = count_tokens(&model, &msg.content);
+        if tokens < MIN_ELIDE_TOKENS {
+            continue
+        }
+        msg.content = format!("{}（约 {} tokens）]", ELIDED_PREFIX, tokens);
+        total = total - tokens + count_tokens(&model, &msg.content);
+        elided += 1;
+    }
+    if elided > 0 {
+        actions.push(format!("省略 {} 段过期的工具输出", elided));
+    }
+
+    // --- [ 2. 摘要较早的轮次 ] ---
+    if total > budget.target
+        && let Some(last_user) = last_user {
+        let older: Vec<usize> = (head..last_user).filter(|&i| Some(i) != spec).collect();
+        if !older.is_empty() {
+            let kept_spec = spec.filter(|&i| i >= head && i < last_user).map(|i| messages[i].clone());
+            notify(format!("{} 上下文约 {} tokens，正在由 BALTHAZAR 摘要较早的对话...", model.name(), total));
+            let summarized = summarize(&older.iter().map(|&i| &messages[i]).collect::<Vec<_>>());
+            match summarized {
+                Ok(summary) => {
+                    let mut rebuilt = messages[..head].to_vec();
+                    rebuilt.push(ChatMessage { role: "system".into(), content: format!("{}\n{}", SUMMARY_PREFIX, summary.trim()) });
+                    rebuilt.extend(kept_spec);
+                    rebuilt.extend_from_slice(&messages[last_user..]);
+                    *messages = rebuilt;
+                    actions.push(format!("由 BALTHAZAR 将 {} 条较早的消息摘要为 1 条", older.len()));
+                }
+                // --- [ 3. 摘要失败：丢弃最早的消息 ] ---
+                Err(e) => {
+                    let mut dropped = vec![false; messages.len()];
+                    for &i in &older {
+                        if total <= budget.target {
+                            break
+                        }
+                        total -= count_messages(&model, std::slice::from_ref(&messages[i]));
+                        dropped[i] = true;
+                    }
+                    let count = dropped.iter().filter(|&&d| d).count();
+                    let mut index = 0;
+                    messages.retain(|_| {
+                        index += 1;
+                        !dropped[index - 1]
+                    });
+                    actions.push(format!("摘要失败（{}），丢弃 {} 条最早的消息", e, count));
+                }
+            }
+        }
+    }
+
+    if actions.is_empty() {
+        notify(format!("{} 上下文约 {} tokens，超过预算 {}，但没有可压缩的内容", model.name(), before, budget.trigger));
+        return
+    }
+    notify(format!(
+        "{} 上下文已压缩: {} → {} tokens（{}）",
+        model.name(), before, count_messages(&model, messages), actions.join("，"),
+    ))
+}
+
+/// CASPER_I 输出的规格书：assistant 消息中以行首 ```spec 开始的代码块
+fn is_spec(msg: &ChatMessage) -> bool {
+    msg.role == "assistant" && msg.content.lines().any(|line| line.starts_with(SPEC_FENCE))
+}
+
+/// 工具输出以 system 消息记录；摘要与已省略的占位不算在内
+fn is_tool_result(msg: &ChatMessage) -> bool {
+    msg.role == "system" && !msg.content.starts_with(SUMMARY_PREFIX) && !msg.content.starts_with(ELIDED_PREFIX)
+}
+
+/// 请 BALTHAZAR 摘要一组消息
+fn summarize(client: &ApiClient, messages: &[&ChatMessage]) -> Result<String, String> {
+    let mut transcript = String::new();
+    for msg in messages {
+        let content: String = msg.content.chars().take(SUMMARY_MESSAGE_CHARS).collect();
+        transcript.push_str(&format!("[{}]\n{}\n\n", msg.role, content));
+    }
+
+    // 摘要请求本身也不能超过 BALTHAZAR 的预算，超出时只保留较新的部分
+    let limit = Budget::of(&Model::BALTHAZAR).target;
+    let tokens = count_tokens(&Model::BALTHAZAR, &transcript);
+    if tokens > limit {
+        let chars = transcript.chars().count();
+        transcript = transcript.chars().skip(chars - chars * limit / tokens).collect();
+    }
+
+    let request = vec![
+        ChatMessage { role: "system".into(), content: SUMMARY_PROMPT.into() },
+        ChatMessage { role: "user".into(), content: transcript },
+    ];
+    let summary = client.complete(Model::BALTHAZAR, request)?;
+    if summary.trim().is_empty() {
+        return Err("BALTHAZAR 返回了空摘要".into())
+    }
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use super::*;
+    use crate::config_manager::init_test_config;
+
+    const MODEL: Model = Model::CASPER_II;
+
+    fn message(role: &str, content: &str) -> ChatMessage {
+        ChatMessage { role: role.into(), content: content.into() }
+    }
+
+    /// 超过 MIN_ELIDE_TOKENS 的工具输出
+    fn tool_result(name: &str) -> ChatMessage {
+        let content = format!("System: Exec Result {}\n{}", name, "output line\n".repeat(200));
+        assert!(count_tokens(&MODEL, &content) >= MIN_ELIDE_TOKENS);
+        message("system", &content)
+    }
+
+    fn is_elided(msg: &ChatMessage) -> bool {
+        msg.content.starts_with(ELIDED_PREFIX)
+    }
+
+    fn no_summary(_: &[&ChatMessage]) -> Result<String, String> {
+        panic!("不应进入摘要步骤")
+    }
+
+    #[test]
+    fn under_budget_is_untouched() {
+        init_test_config();
+        let mut messages = vec![message("system", "提示词"), message("user", "问题"), tool_result("a")];
+        let before = count_messages(&MODEL, &messages);
+        let notes = RefCell::new(Vec::new());
+        shrink(MODEL, &mut messages, Budget { trigger: before, target: 0 }, &no_summary, &|n| notes.borrow_mut().push(n));
+
+        assert!(!is_elided(&messages[2]));
+        assert!(notes.borrow().is_empty());
+    }
+
+    #[test]
+    fn elides_oldest_tool_results_first_and_stops_at_target() {
+        init_test_config();
+        let summary = format!("{}\n{}", SUMMARY_PREFIX, "earlier work\n".repeat(200));
+        let mut messages = vec![
+            message("system", "提示词"),
+            message("system", &summary),
+            message("user", "第一轮"),
+            tool_result("a"),
+            tool_result("b"),
+            tool_result("c"),
+            message("user", "第二轮"),
+            tool_result("d"),
+            tool_result("e"),
+            tool_result("f"),
+        ];
+        let before = count_messages(&MODEL, &messages);
+        // 只需省略两段即可回到目标以下
+        let elided = count_tokens(&MODEL, &format!("{}（约 {} tokens）]", ELIDED_PREFIX, count_tokens(&MODEL, &messages[3].content)));
+        let target = before - 2 * (count_tokens(&MODEL, &messages[3].content) - elided);
+        shrink(MODEL, &mut messages, Budget { trigger: before - 1, target }, &no_summary, &|_| {});
+
+        assert_eq!(messages.len(), 10);
+        assert_eq!(messages[1].content, summary);
+        assert!(is_elided(&messages[3]) && is_elided(&messages[4]));
+        assert!(messages[5..].iter().all(|m| !is_elided(m)));
+    }
+
+    #[test]
+    fn elides_tool_results_within_the_current_turn() {
+        init_test_config();
+        let mut messages = vec![message("system", "提示词"), message("user", "```spec\n- a.rs\n```")];
+        for name in ["a", "b", "c", "d", "e"] {
+            messages.push(message("assistant", "```exec\nmake\n```"));
+            messages.push(tool_result(name));
+        }
+        let notes = RefCell::new(Vec::new());
+        shrink(MODEL, &mut messages, Budget { trigger: 0, target: 0 }, &no_summary, &|n| notes.borrow_mut().push(n));
+
+        let elided: Vec<bool> = messages.iter().map(is_elided).collect();
+        assert_eq!(elided, vec![false, false, false, true, false, true, false, false, false, false, false, false]);
+        assert!(notes.borrow()[0].contains("省略 2 段过期的工具输出"));
+    }
+
+    #[test]
+    fn summarizes_after_eliding_and_keeps_spec_and_last_turn() {
+        init_test_config();
+        let spec = "```spec\n- a.rs: 修改 main\n```";
+        let mut messages = vec![
+            message("system", "提示词"),
+            message("user", "第一轮"),
+            tool_result("a"),
+            message("assistant", spec),
+            message("user", "第二轮"),
+            tool_result("b"),
+            tool_result("c"),
+            tool_result("d"),
+        ];
+        let seen = RefCell::new(Vec::new());
+        let summarize = |older: &[&ChatMessage]| {
+            seen.borrow_mut().extend(older.iter().map(|m| m.content.clone()));
+            Ok("摘要内容".to_string())
+        };
+        let notes = RefCell::new(Vec::new());
+        shrink(MODEL, &mut messages, Budget { trigger: 0, target: 0 }, &summarize, &|n| notes.borrow_mut().push(n));
+
+        // 第 1 步先于摘要：交给摘要的工具输出已是占位说明，spec 不参与摘要
+        let seen = seen.into_inner();
+        assert_eq!(seen.len(), 2);
+        assert_eq!(seen[0], "第一轮");
+        assert!(seen[1].starts_with(ELIDED_PREFIX));
+
+        let contents: Vec<&str> = messages.iter().map(|m| m.content.as_str()).collect();
+        assert_eq!(contents[..4], ["提示词", &format!("{}\n摘要内容", SUMMARY_PREFIX), spec, "第二轮"]);
+        assert_eq!(messages.len(), 7);
+        assert!(messages[4..].iter().all(|m| !is_elided(m)));
+        assert!(notes.borrow().last().unwrap().contains("由 BALTHAZAR 将 2 条较早的消息摘要为 1 条"));
+    }
+
+    #[test]
+    fn drops_oldest_messages_when_summary_fails() {
+        init_test_config();
+        let mut messages = vec![
+            message("system", "提示词"),
+            message("user", "第一轮"),
+            message("assistant", "回答一"),
+            message("user", "第二轮"),
+            message("assistant", "回答二"),
+            message("user", "第三轮"),
+        ];
+        let before = count_messages(&MODEL, &messages);
+        let target = before - count_messages(&MODEL, &messages[1..3]);
+        let notes = RefCell::new(Vec::new());
+        shrink(MODEL, &mut messages, Budget { trigger: target, target }, &|_| Err("网络错误".into()), &|n| notes.borrow_mut().push(n));
+
+        let contents: Vec<&str> = messages.iter().map(|m| m.content.as_str()).collect();
+        assert_eq!(contents, ["提示词", "第二轮", "回答二", "第三轮"]);
+        assert!(notes.borrow().last().unwrap().contains("摘要失败（网络错误），丢弃 2 条最早的消息"));
+    }
+
+    #[test]
+    fn fence_in_tool_output_is_not_a_spec() {
+        init_test_config();
+        let spec = "方案如下：\n```spec\n- a.rs: 修改 main\n```";
+        let prompt = message("system", &format!("System: Read Result prompt/CASPER_I_PROMPT.md:\n```spec\n{}", "示例\n".repeat(300)));
+        let mut messages = vec![
+            message("system", "提示词"),
+            message("user", "第一轮"),
+            message("assistant", spec),
+            message("assistant", "说明里提到 ```spec 但不在行首"),
+            prompt,
+            message("user", "第二轮"),
+            tool_result("b"),
+            tool_result("c"),
+            tool_result("d"),
+        ];
+        let seen = RefCell::new(Vec::new());
+        let summarize = |older: &[&ChatMessage]| {
+            seen.borrow_mut().extend(older.iter().map(|m| m.content.clone()));
+            Ok("摘要内容".to_string())
+        };
+        shrink(MODEL, &mut messages, Budget { trigger: 0, target: 0 }, &summarize, &|_| {});
+
+        // 含标记的读取结果照常省略并参与摘要，真正的规格书被保留
+        let seen = seen.into_inner();
+        assert_eq!(seen.len(), 3);
+        assert!(seen[2].starts_with(ELIDED_PREFIX));
+        assert_eq!(messages[2].content, spec);
+        assert_eq!(messages[3].content, "第二轮");
+    }
+}
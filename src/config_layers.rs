@@ -0,0 +1,336 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::sync::OnceLock;
+use serde_json::{Map, Value};
+
+/*
+ * -------- [ 分层配置 ] --------
+ * 按以下顺序合并，后者覆盖前者：
+ *   内置默认值 -> ~/.oxicodent/config.json -> ./.oxicodent/config.json -> 环境变量 -> 命令行参数
+ * - 对象逐键合并；带 name 字段的对象数组（providers）按 name 合并，其余数组整体替换
+ * - 每个叶子值记录其来源，供 `config show` 展示
+ */
+#[derive(Debug, Default)]
+pub struct Layered {
+    pub value: Value,
+    // 叶子路径（如 roles.melchior.model、providers[ollama].api_base）-> 来源
+    pub sources: BTreeMap<String, String>,
+}
+
+impl Layered {
+    pub fn new() -> Self {
+        Self { value: Value::Object(Map::new()), sources: BTreeMap::new() }
+    }
+
+    /// 合并一层配置
+    pub fn merge(&mut self, layer: Value, source: &str) {
+        merge_value(&mut self.value, layer, source, "", &mut self.sources);
+    }
+
+    /*
+     * -------- [ 合并结果展示 ] --------
+     * 每行一个叶子值及其来源，密钥类字段打码
+     */
+    pub fn describe(&self) -> String {
+        let mut leaves = Vec::new();
+        collect_leaves(&self.value, "", &mut leaves);
+
+        let width = leaves.iter().map(|(path, _)| path.len()).max().unwrap_or(0);
+        let mut output = String::new();
+        for (path, value) in leaves {
+            let shown = if is_secret(&path) {
+                mask_secret(value.as_str().unwrap_or_default())
+            } else {
+                value.to_string()
+            };
+            let source = self.sources.get(&path).map(String::as_str).unwrap_or("默认值");
+            output.push_str(&format!("{:<width$} = {}    ({})\n", path, shown, source));
+        }
+        output
+    }
+}
+
+fn merge_value(base: &mut Value, layer: Value, source: &str, path: &str, sources: &mut BTreeMap<String, String>) {
+    match (base, layer) {
+        (Value::Object(base), Value::Object(layer)) => {
+            for (key, value) in layer {
+                let child = join_path(path, &key);
+                match base.get_mut(&key) {
+                    Some(existing) => merge_value(existing, value, source, &child, sources),
+                    None => {
+                        record_leaves(&value, &child, source, sources);
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (Value::Array(base), Value::Array(layer)) if is_named_list(base) && is_named_list(&layer) => {
+            for mut item in layer {
+                let name = item["name"].as_str().unwrap_or_default().to_string();
+                let child = format!("{}[{}]", path, name);
+                match base.iter_mut().find(|b| b["name"] == name.as_str()) {
+                    Some(existing) => {
+                        // name 仅用于匹配，保留其原始来源
+                        if let Value::Object(fields) = &mut item {
+                            fields.remove("name");
+                        }
+                        merge_value(existing, item, source, &child, sources)
+                    }
+                    None => {
+                        record_leaves(&item, &child, source, sources);
+                        base.push(item);
+                    }
+                }
+            }
+        }
+        (base, layer) => {
+            // 整体替换：清除旧值下各叶子的来源
+            sources.retain(|p, _| !(p == path || p.starts_with(&format!("{}.", path)) || p.starts_with(&format!("{}[", path))));
+            record_leaves(&layer, path, source, sources);
+            *base = layer;
+        }
+    }
+}
+
+fn join_path(path: &str, key: &str) -> String {
+    if path.is_empty() { key.to_string() } else { format!("{}.{}", path, key) }
+}
+
+fn is_named_list(items: &[Value]) -> bool {
+    items.iter().all(|item| item["name"].is_string())
+}
+
+fn record_leaves(value: &Value, path: &str, source: &str, sources: &mut BTreeMap<String, String>) {
+    let mut leaves = Vec::new();
+    collect_leaves(value, path, &mut leaves);
+    for (leaf, _) in leaves {
+        sources.insert(leaf, source.to_string());
+    }
+}
+
+fn collect_leaves<'a>(value: &'a Value, path: &str, leaves: &mut Vec<(String, &'a Value)>) {
+    match value {
+        Value::Object(map) if !map.is_empty() => {
+            for (key, child) in map {
+                collect_leaves(child, &join_path(path, key), leaves);
+            }
+        }
+        Value::Array(items) if !items.is_empty() && is_named_list(items) => {
+            for item in items {
+                collect_leaves(item, &format!("{}[{}]", path, item["name"].as_str().unwrap_or_default()), leaves);
+            }
+        }
+        _ => leaves.push((path.to_string(), value)),
+    }
+}
+
+// --- [ 密钥打码 ] ---
+// api_key_env / api_key_cmd 只是密钥的来源，不打码
+fn is_secret(path: &str) -> bool {
+    let key = path.rsplit('.').next().unwrap_or(path);
+    key.ends_with("api_key") || key.ends_with("token") || key.contains("secret")
+}
+
+/// 仅保留末 4 位，过短的密钥完全隐藏
+pub fn mask_secret(secret: &str) -> String {
+    let chars: Vec<char> = secret.chars().collect();
+    match chars.len() {
+        0 => "\"\"".into(),
+        1..=8 => "\"****\"".into(),
+        n => format!("\"****{}\"", chars[n - 4..].iter().collect::<String>()),
+    }
+}
+
+/*
+ * -------- [ 覆盖层 ] --------
+ * 环境变量与命令行参数共用同一结构：
+ * - api_key / api_base 作用于 provider 指定的 provider，未指定时作用于 MELCHIOR 所用的 provider
+ * - models 覆盖角色模型
+ * - assignments 为命令行 `--set a.b=value`，最后合并
+ */
+#[derive(Default)]
+pub struct Overrides {
+    pub provider: Option<String>,
+    pub api_key: Option<String>,
+    pub api_base: Option<String>,
+    pub models: Vec<(&'static str, String)>,
+    pub theme: Option<String>,
+    pub context_window: Option<usize>,
+    pub assignments: Vec<Value>,
+}
+
+const ROLE_KEYS: [&str; 4] = ["melchior", "casper_i", "casper_ii", "balthazar"];
+
+impl Overrides {
+    /*
+     * 环境变量：
+     *   OXICODENT_PROVIDER / OXICODENT_API_KEY / OXICODENT_API_BASE
+     *   OXICODENT_<ROLE>_MODEL（ROLE 为 MELCHIOR / CASPER_I / CASPER_II / BALTHAZAR）
+     *   OXICODENT_THEME / OXICODENT_CONTEXT_WINDOW
+     */
+    pub fn from_env() -> Result<Self, String> {
+        let var = |name: &str| env::var(name).ok().filter(|v| !v.is_empty());
+        let context_window = var("OXICODENT_CONTEXT_WINDOW")
+            .map(|v| v.parse().map_err(|_| format!("OXICODENT_CONTEXT_WINDOW 不是有效的数字: {}", v)))
+            .transpose()?;
+
+        Ok(Self {
+            provider: var("OXICODENT_PROVIDER"),
+            api_key: var("OXICODENT_API_KEY"),
+            api_base: var("OXICODENT_API_BASE"),
+            models: ROLE_KEYS.iter()
+                .filter_map(|role| var(&format!("OXICODENT_{}_MODEL", role.to_uppercase())).map(|m| (*role, m)))
+                .collect(),
+            theme: var("OXICODENT_THEME"),
+            context_window,
+            assignments: Vec::new(),
+        })
+    }
+
+    /// 生成覆盖层；merged 为此前各层的合并结果，用于确定默认 provider
+    pub fn layer(&self, merged: &Value) -> Value {
+        let mut layer = Layered::new();
+
+        let provider = self.provider.clone()
+            .or_else(|| merged["roles"]["melchior"]["provider"].as_str().map(String::from));
+        let mut provider_fields = Map::new();
+        if let Some(key) = &self.api_key {
+            provider_fields.insert("api_key".into(), key.clone().into());
+        }
+        if let Some(base) = &self.api_base {
+            provider_fields.insert("api_base".into(), base.clone().into());
+        }
+        if let Some(provider) = provider && !provider_fields.is_empty() {
+            provider_fields.insert("name".into(), provider.into());
+            layer.merge(serde_json::json!({ "providers": [provider_fields] }), "");
+        }
+
+        for (role, model) in &self.models {
+            layer.merge(serde_json::json!({ "roles": { *role: { "model": model } } }), "");
+        }
+        if let Some(theme) = &self.theme {
+            layer.merge(serde_json::json!({ "theme": theme }), "");
+        }
+        if let Some(window) = self.context_window {
+            layer.merge(serde_json::json!({ "context_window": window }), "");
+        }
+        for assignment in &self.assignments {
+            layer.merge(assignment.clone(), "");
+        }
+
+        layer.value
+    }
+}
+
+/*
+ * -------- [ 命令行参数层 ] --------
+ * 启动时设置一次，/config reload 时沿用
+ */
+static CLI_OVERRIDES: OnceLock<Overrides> = OnceLock::new();
+
+pub fn set_cli_overrides(overrides: Overrides) {
+    let _ = CLI_OVERRIDES.set(overrides);
+}
+
+pub fn cli_overrides() -> Option<&'static Overrides> {
+    CLI_OVERRIDES.get()
+}
+
+/// 将 `a.b.c=value` 展开为嵌套对象；value 能按 JSON 解析时取解析结果，否则视为字符串
+pub fn parse_assignment(assignment: &str) -> Result<Value, String> {
+    let (path, raw) = assignment.split_once('=')
+        .ok_or(format!("无效的配置项 `{}`，应为 key=value", assignment))?;
+    if path.is_empty() || path.split('.').any(str::is_empty) {
+        return Err(format!("无效的配置路径 `{}`", path))
+    }
+
+    let value = serde_json::from_str(raw).unwrap_or_else(|_| Value::String(raw.to_string()));
+    Ok(path.rsplit('.').fold(value, |value, key| {
+        let mut map = Map::new();
+        map.insert(key.to_string(), value);
+        Value::Object(map)
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn merge_objects_key_by_key_and_track_sources() {
+        let mut layered = Layered::new();
+        layered.merge(json!({ "theme": "a", "roles": { "melchior": { "model": "m1", "provider": "p" } } }), "默认值");
+        layered.merge(json!({ "roles": { "melchior": { "model": "m2" } } }), "用户配置");
+
+        assert_eq!(layered.value, json!({ "theme": "a", "roles": { "melchior": { "model": "m2", "provider": "p" } } }));
+        assert_eq!(layered.sources["theme"], "默认值");
+        assert_eq!(layered.sources["roles.melchior.model"], "用户配置");
+        assert_eq!(layered.sources["roles.melchior.provider"], "默认值");
+    }
+
+    #[test]
+    fn merge_named_lists_by_name() {
+        let mut layered = Layered::new();
+        layered.merge(json!({ "providers": [
+            { "name": "local", "api_base": "http://a" },
+            { "name": "remote", "api_base": "http://b", "api_key": "k" },
+        ] }), "用户配置");
+        layered.merge(json!({ "providers": [
+            { "name": "remote", "api_base": "http://c" },
+            { "name": "extra", "api_base": "http://d" },
+        ] }), "项目配置");
+
+        assert_eq!(layered.value["providers"], json!([
+            { "name": "local", "api_base": "http://a" },
+            { "name": "remote", "api_base": "http://c", "api_key": "k" },
+            { "name": "extra", "api_base": "http://d" },
+        ]));
+        assert_eq!(layered.sources["providers[remote].api_base"], "项目配置");
+        assert_eq!(layered.sources["providers[remote].api_key"], "用户配置");
+        // name 只用于匹配，不改变来源
+        assert_eq!(layered.sources["providers[remote].name"], "用户配置");
+        assert_eq!(layered.sources["providers[extra].name"], "项目配置");
+    }
+
+    #[test]
+    fn merge_replaces_other_values_and_clears_stale_sources() {
+        let mut layered = Layered::new();
+        layered.merge(json!({ "stop": ["a", "b"], "extra": { "x": 1, "y": 2 } }), "用户配置");
+        layered.merge(json!({ "stop": ["c"], "extra": "none" }), "命令行参数");
+
+        assert_eq!(layered.value, json!({ "stop": ["c"], "extra": "none" }));
+        assert_eq!(layered.sources["stop"], "命令行参数");
+        assert_eq!(layered.sources["extra"], "命令行参数");
+        assert!(!layered.sources.contains_key("extra.x"));
+    }
+
+    #[test]
+    fn parse_assignment_nests_path_and_parses_json() {
+        assert_eq!(parse_assignment("roles.melchior.model=qwen3:14b").unwrap(), json!({ "roles": { "melchior": { "model": "qwen3:14b" } } }));
+        assert_eq!(parse_assignment("context_window=8192").unwrap(), json!({ "context_window": 8192 }));
+        assert_eq!(parse_assignment("keep_reasoning=true").unwrap(), json!({ "keep_reasoning": true }));
+        assert_eq!(parse_assignment("roles.casper_ii.stop=[\"```\"]").unwrap(), json!({ "roles": { "casper_ii": { "stop": ["```"] } } }));
+        // 值中的 = 保留
+        assert_eq!(parse_assignment("theme=a=b").unwrap(), json!({ "theme": "a=b" }));
+        assert_eq!(parse_assignment("theme=").unwrap(), json!({ "theme": "" }));
+    }
+
+    #[test]
+    fn parse_assignment_rejects_invalid_input() {
+        assert!(parse_assignment("theme").is_err());
+        assert!(parse_assignment("=x").is_err());
+        assert!(parse_assignment("roles..model=x").is_err());
+        assert!(parse_assignment("roles.=x").is_err());
+    }
+
+    #[test]
+    fn describe_masks_secrets() {
+        let mut layered = Layered::new();
+        layered.merge(json!({ "providers": [{ "name": "p", "api_key": "sk-1234567890", "api_key_env": "KEY" }] }), "用户配置");
+        let output = layered.describe();
+        assert!(output.contains("\"****7890\""));
+        assert!(!output.contains("sk-1234567890"));
+        assert!(output.contains("\"KEY\""));
+    }
+}
@@ -1,65 +1,698 @@
 use serde::{Serialize, Deserialize};
-use std::path::PathBuf;
+use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::collections::BTreeMap;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, OnceLock, RwLock};
 use std::{fs, env};
+use tracing::{info, warn};
+use crate::config_layers::{cli_overrides, Layered, Overrides};
+use crate::redact::{add_secret, register_secrets};
+use crate::tokenizer;
+use crate::app::Model;
 
 const ROOT_DIR: &str = ".oxicodent";
 const CONFIG_FILENAME: &str = "config.json";
+const DEFAULT_THEME: &str = "base16-ocean.dark";
+const DEFAULT_CONTEXT_WINDOW: usize = 32768;
 
-fn get_home_path() -> Result<PathBuf, String> {
+static CONFIG: OnceLock<RwLock<Config>> = OnceLock::new();
+
+/// 全局配置，需先调用 Config::init
+pub fn get_config() -> &'static RwLock<Config> {
+    CONFIG.get().expect("配置尚未初始化")
+}
+
+/// 测试用：以单 provider 的模板配置初始化全局配置，已初始化时保持不变
+#[cfg(test)]
+pub fn init_test_config() {
+    let provider = Provider::new("test", ProviderKind::OpenAI, "http://127.0.0.1:9/v1/chat/completions".into(), String::new());
+    let _ = CONFIG.set(RwLock::new(Config::template(provider, "melchior", "casper", "balthazar")));
+}
+
+pub fn get_home_path() -> Result<PathBuf, String> {
     let mut path = env::home_dir().expect("无法获得用户主目录");
     path.push(ROOT_DIR);
-    if !path.exists() {
-        if let Err(e) = fs::create_dir_all(&path) {
-            return Err(format!("无法创建应用配置文件目录 <{}>: {}", &path.to_string_lossy(), e))
-        }
+    if !path.exists() && let Err(e) = fs::create_dir_all(&path) {
+        return Err(format!("无法创建应用配置文件目录 <{}>: {}", &path.to_string_lossy(), e))
+    }
+
+    Ok(path)
+}
+
+/// 当前工作区下的 .oxicodent 目录（会话等项目级数据）
+pub fn get_workspace_path() -> Result<PathBuf, String> {
+    let path = PathBuf::from(ROOT_DIR);
+    if !path.exists() && let Err(e) = fs::create_dir_all(&path) {
+        return Err(format!("无法创建工作区目录 <{}>: {}", &path.to_string_lossy(), e))
     }
 
     Ok(path)
 }
 
+/*
+ * -------- [ 配置结构 ] --------
+ * - providers 为可用的模型服务（名称、地址、密钥、类型）
+ * - roles 将每个角色映射到某个 provider 上的模型
+ * - 旧版扁平配置（api_key / api_base / *_model）载入时自动迁移
+ * - 各层配置的合并顺序见 config_layers
+ */
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Config {
-    pub api_key: String,
+    pub providers: Vec<Provider>,
+    pub roles: Roles,
+    #[serde(default = "default_theme")]
+    pub theme: String, // 代码高亮主题（syntect 内置主题名）
+    #[serde(default = "default_context_window")]
+    pub context_window: usize, // 模型上下文窗口大小（token）
+    #[serde(default)]
+    pub keep_reasoning: bool, // 是否将思考过程（<think> 等）保留在上下文中
+    #[serde(default)]
+    pub tool_policy: ToolPolicy, // 非交互模式（ask / run）下工具调用的处理方式
+    // 按模型名配置的单价，用于统计费用
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub prices: BTreeMap<String, Price>,
+    // 按模型名指定本地分词器文件，用于统计上下文 token 数
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub tokenizers: BTreeMap<String, TokenizerConfig>,
+    // 合并前的各层配置与来源，仅用于 `config show`
+    #[serde(skip)]
+    pub layered: Layered,
+    // 载入过程中的警告（如配置文件权限过宽），启动后显示在对话区
+    #[serde(skip)]
+    pub warnings: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Provider {
+    pub name: String,
+    pub kind: ProviderKind,
     pub api_base: String, // 方便支持 Ollama 或自定义代理
-    pub melchior_model: String,
-    pub casper_model: String,
-    pub balthazar_model: String,
+    // 密钥来源优先级：api_key > api_key_env > api_key_cmd，通过 key() 在首次使用时解析
+    #[serde(default)]
+    pub api_key: String,
+    // 从环境变量读取密钥
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub api_key_env: Option<String>,
+    // 执行命令并取其输出作为密钥，如 `pass show openai`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub api_key_cmd: Option<String>,
+    // OpenAI 兼容接口是否请求 stream_options.include_usage；已知不支持该字段的服务可直接设为 false
+    #[serde(default = "default_stream_usage")]
+    pub stream_usage: bool,
+    // 解析后的密钥，不序列化，避免 save() 写入磁盘；克隆之间共享
+    #[serde(skip)]
+    resolved_key: Arc<OnceLock<Result<String, String>>>,
+    // 服务端以 400 拒绝过 stream_options，之后的请求不再携带；克隆之间共享
+    #[serde(skip)]
+    stream_usage_rejected: Arc<AtomicBool>,
+}
+
+impl Provider {
+    pub fn new(name: &str, kind: ProviderKind, api_base: String, api_key: String) -> Self {
+        Self { name: name.into(), kind, api_base, api_key, api_key_env: None, api_key_cmd: None, stream_usage: true, resolved_key: Arc::default(), stream_usage_rejected: Arc::default() }
+    }
+
+    /// 请求使用的密钥：首次调用时解析并登记脱敏，之后返回缓存结果
+    pub fn key(&self) -> Result<String, String> {
+        self.resolved_key.get_or_init(|| {
+            let key = self.resolve_key()?;
+            add_secret(&key);
+            Ok(key)
+        }).clone()
+    }
+
+    /// 是否请求流式用量统计
+    pub fn stream_usage(&self) -> bool {
+        self.stream_usage && !self.stream_usage_rejected.load(Ordering::Relaxed)
+    }
+
+    /// 记录服务端不支持 stream_options
+    pub fn reject_stream_usage(&self) {
+        self.stream_usage_rejected.store(true, Ordering::Relaxed);
+    }
+
+    /// api_key 为空时，依次从 api_key_env、api_key_cmd 获取
+    fn resolve_key(&self) -> Result<String, String> {
+        if !self.api_key.is_empty() {
+            return Ok(self.api_key.clone())
+        }
+
+        if let Some(var) = &self.api_key_env {
+            env::var(var).map_err(|_| format!("provider <{}> 的环境变量 {} 未设置", self.name, var))
+        } else if let Some(cmd) = &self.api_key_cmd {
+            info!("执行 provider <{}> 的 api_key_cmd", self.name);
+            let output = Command::new("sh").arg("-c").arg(cmd).output()
+                .map_err(|e| format!("provider <{}> 的 api_key_cmd 执行失败: {}", self.name, e))?;
+            if !output.status.success() {
+                return Err(format!("provider <{}> 的 api_key_cmd 执行失败: {}", self.name, output.status))
+            }
+            // 只取第一行，兼容 `pass show` 等多行输出
+            Ok(String::from_utf8_lossy(&output.stdout).lines().next().unwrap_or("").trim().to_string())
+        } else {
+            Ok(String::new())
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ProviderKind {
+    // OpenAI 兼容接口（/v1/chat/completions）
+    OpenAI,
+    // 本地 Ollama，经由其 OpenAI 兼容接口访问
+    Ollama,
+    // 本地 Ollama 原生接口（/api/chat），可通过 num_ctx 设置上下文窗口
+    #[serde(rename = "ollama-native")]
+    OllamaNative,
+    // Anthropic Messages 接口（/v1/messages）
+    Anthropic,
+}
+
+impl ProviderKind {
+    pub const ALL: [ProviderKind; 4] = [ProviderKind::Ollama, ProviderKind::OllamaNative, ProviderKind::OpenAI, ProviderKind::Anthropic];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            ProviderKind::OpenAI => "OpenAI 兼容接口",
+            ProviderKind::Ollama => "Ollama（本地）",
+            ProviderKind::OllamaNative => "Ollama 原生接口（本地）",
+            ProviderKind::Anthropic => "Anthropic Messages 接口",
+        }
+    }
+
+    /// 服务根地址的默认值
+    pub fn default_root(&self) -> &'static str {
+        match self {
+            ProviderKind::OpenAI => "https://api.openai.com",
+            ProviderKind::Ollama | ProviderKind::OllamaNative => "http://127.0.0.1:11434",
+            ProviderKind::Anthropic => "https://api.anthropic.com",
+        }
+    }
+
+    /// 对话接口地址（即 Provider::api_base）
+    pub fn chat_url(&self, root: &str) -> String {
+        match self {
+            ProviderKind::OpenAI | ProviderKind::Ollama => format!("{}/v1/chat/completions", root),
+            ProviderKind::OllamaNative => format!("{}/api/chat", root),
+            ProviderKind::Anthropic => format!("{}/v1/messages", root),
+        }
+    }
+
+    /// 模型列表接口地址
+    pub fn models_url(&self, root: &str) -> String {
+        match self {
+            ProviderKind::OpenAI | ProviderKind::Anthropic => format!("{}/v1/models", root),
+            ProviderKind::Ollama | ProviderKind::OllamaNative => format!("{}/api/tags", root),
+        }
+    }
+}
+
+/// 去掉接口路径，得到服务根地址：`http://host:11434/v1/chat/completions` -> `http://host:11434`
+pub fn service_root(api_base: &str) -> String {
+    let base = api_base.trim().trim_end_matches('/');
+    let base = ["/chat/completions", "/messages", "/api/chat"].iter()
+        .find_map(|suffix| base.strip_suffix(suffix))
+        .unwrap_or(base);
+    base.strip_suffix("/v1").unwrap_or(base).to_string()
+}
+
+/// 模型单价（美元 / 百万 token）
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+pub struct Price {
+    pub input: f64,
+    pub output: f64,
+}
+
+impl Price {
+    pub fn cost(&self, prompt_tokens: u64, completion_tokens: u64) -> f64 {
+        (prompt_tokens as f64 * self.input + completion_tokens as f64 * self.output) / 1_000_000.0
+    }
+}
+
+/// 本地分词器文件
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum TokenizerConfig {
+    // HuggingFace tokenizer.json
+    Huggingface { path: String },
+    // tiktoken BPE 文件；pattern 为 cl100k / o200k 或自定义正则，缺省时按文件名判断
+    Tiktoken {
+        path: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pattern: Option<String>,
+    },
+}
+
+/*
+ * -------- [ 工具调用策略 ] --------
+ * 非交互模式下无法逐个确认，按策略处理 exec 与 diff；read 始终执行。
+ * 命令行的 --yes 等同于 auto
+ */
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ToolPolicy {
+    // 拒绝 exec 与 diff
+    #[default]
+    Deny,
+    // 应用 diff，拒绝 exec
+    Patch,
+    // 全部执行
+    Auto,
+}
+
+impl ToolPolicy {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ToolPolicy::Deny => "deny",
+            ToolPolicy::Patch => "patch",
+            ToolPolicy::Auto => "auto",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Roles {
+    pub melchior: RoleConfig,
+    pub casper_i: RoleConfig,
+    pub casper_ii: RoleConfig,
+    pub balthazar: RoleConfig,
+}
+
+/*
+ * -------- [ 角色配置 ] --------
+ * 采样参数未设置时不发送，由服务端使用默认值；
+ * extra 原样合并进请求体，用于 provider 特有参数（如 Ollama 的 options.num_ctx、keep_alive）
+ */
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RoleConfig {
+    pub provider: String,
+    pub model: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub top_p: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_tokens: Option<u32>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub stop: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seed: Option<u64>,
+    // 覆盖全局 context_window，用于上下文预算与 Ollama 的 num_ctx
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub context_window: Option<usize>,
+    #[serde(default, skip_serializing_if = "serde_json::Map::is_empty")]
+    pub extra: serde_json::Map<String, serde_json::Value>,
+}
+
+// 请求体中由客户端填写的字段，不允许通过 extra 覆盖
+const RESERVED_FIELDS: &[&str] = &["model", "messages", "stream", "temperature", "top_p", "max_tokens", "stop", "seed", "system", "stream_options"];
+
+impl RoleConfig {
+    fn new(provider: &str, model: &str) -> Self {
+        Self {
+            provider: provider.into(),
+            model: model.into(),
+            temperature: None,
+            top_p: None,
+            max_tokens: None,
+            stop: Vec::new(),
+            seed: None,
+            context_window: None,
+            extra: serde_json::Map::new(),
+        }
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        if self.model.trim().is_empty() {
+            return Err("model 不能为空".into())
+        }
+        if let Some(t) = self.temperature && !(0.0..=2.0).contains(&t) {
+            return Err(format!("temperature 应在 0 ~ 2 之间: {}", t))
+        }
+        if let Some(p) = self.top_p && !(p > 0.0 && p <= 1.0) {
+            return Err(format!("top_p 应在 (0, 1] 之间: {}", p))
+        }
+        if self.max_tokens == Some(0) {
+            return Err("max_tokens 应大于 0".into())
+        }
+        if self.context_window == Some(0) {
+            return Err("context_window 应大于 0".into())
+        }
+        if self.stop.iter().any(|s| s.is_empty()) {
+            return Err("stop 中不能有空字符串".into())
+        }
+        if let Some(key) = self.extra.keys().find(|k| RESERVED_FIELDS.contains(&k.as_str())) {
+            return Err(format!("extra 不能覆盖字段 {}", key))
+        }
+        Ok(())
+    }
+}
+
+/// 旧版扁平配置，仅用于迁移
+#[derive(Deserialize)]
+struct LegacyConfig {
+    api_key: String,
+    api_base: String,
+    melchior_model: String,
+    casper_model: String,
+    balthazar_model: String,
+    #[serde(default = "default_theme")]
+    theme: String,
+    #[serde(default = "default_context_window")]
+    context_window: usize,
+}
+
+impl From<LegacyConfig> for Config {
+    fn from(legacy: LegacyConfig) -> Self {
+        const PROVIDER: &str = "default";
+        let role = |model: String| RoleConfig::new(PROVIDER, &model);
+        Config {
+            providers: vec![Provider::new(PROVIDER, ProviderKind::OpenAI, legacy.api_base, legacy.api_key)],
+            roles: Roles {
+                melchior: role(legacy.melchior_model),
+                casper_i: role(legacy.casper_model.clone()),
+                casper_ii: role(legacy.casper_model),
+                balthazar: role(legacy.balthazar_model),
+            },
+            theme: legacy.theme,
+            context_window: legacy.context_window,
+            keep_reasoning: false,
+            tool_policy: ToolPolicy::default(),
+            prices: BTreeMap::new(),
+            tokenizers: BTreeMap::new(),
+            layered: Layered::new(),
+            warnings: Vec::new(),
+        }
+    }
+}
+
+fn default_theme() -> String {
+    DEFAULT_THEME.into()
+}
+
+fn default_context_window() -> usize {
+    DEFAULT_CONTEXT_WINDOW
+}
+
+fn default_stream_usage() -> bool {
+    true
 }
 
 impl Config {
-    /// 加载配置，如果不存在则引导用户创建
+    /// 角色的配置
+    pub fn role(&self, model: &Model) -> &RoleConfig {
+        match model {
+            Model::MELCHIOR => &self.roles.melchior,
+            Model::CASPER_I => &self.roles.casper_i,
+            Model::CASPER_II => &self.roles.casper_ii,
+            Model::BALTHAZAR => &self.roles.balthazar
+        }
+    }
+
+    /// 角色对应的模型名
+    pub fn model_name(&self, model: &Model) -> &str {
+        &self.role(model).model
+    }
+
+    /// 角色的上下文窗口大小（token），未单独配置时使用全局值
+    pub fn context_window(&self, model: &Model) -> usize {
+        self.role(model).context_window.unwrap_or(self.context_window)
+    }
+
+    /// 角色使用的 provider（载入时已校验存在）
+    pub fn provider(&self, model: &Model) -> &Provider {
+        let name = &self.role(model).provider;
+        self.providers.iter().find(|p| &p.name == name).expect("角色引用了不存在的 provider")
+    }
+
+    /// 校验 provider 名称唯一、地址非空，各角色引用的 provider 存在且采样参数合法，单价非负
+    fn validate(&self) -> Result<(), String> {
+        for (i, provider) in self.providers.iter().enumerate() {
+            if self.providers[..i].iter().any(|p| p.name == provider.name) {
+                return Err(format!("provider 名称重复: {}", provider.name))
+            }
+            if provider.api_base.trim().is_empty() {
+                return Err(format!("provider <{}> 缺少 api_base", provider.name))
+            }
+        }
+
+        for model in Model::ALL {
+            let role = self.role(&model);
+            if !self.providers.iter().any(|p| p.name == role.provider) {
+                return Err(format!("{} 引用了不存在的 provider: {}", model.name(), role.provider))
+            }
+            role.validate().map_err(|e| format!("{}: {}", model.name(), e))?;
+        }
+
+        if let Some((model, _)) = self.prices.iter().find(|(_, p)| p.input < 0.0 || p.output < 0.0) {
+            return Err(format!("模型 {} 的单价不能为负数", model))
+        }
+        Ok(())
+    }
+
+    /// 带有密钥的 provider，其 api_base 不能来自项目配置（见 sanitize_project_layer）
+    fn check_project_endpoints(&self, layered: &Layered, project_source: &str) -> Result<(), String> {
+        for provider in &self.providers {
+            let has_key = !provider.api_key.is_empty() || provider.api_key_env.is_some() || provider.api_key_cmd.is_some();
+            let base_source = layered.sources.get(&format!("providers[{}].api_base", provider.name));
+            if has_key && base_source.is_some_and(|source| source == project_source) {
+                return Err(format!(
+                    "配置错误: {} 设置了带有密钥的 provider <{}> 的 api_base，为防止密钥泄露已拒绝载入；请在用户配置中设置",
+                    project_source, provider.name,
+                ))
+            }
+        }
+        Ok(())
+    }
+
+    /// 加载配置并设为全局配置；重复调用时重新载入
+    pub fn init() -> Result<(), String> {
+        let config = Self::load_or_init()?;
+        match CONFIG.get() {
+            Some(lock) => *lock.write().unwrap() = config,
+            None => { let _ = CONFIG.set(RwLock::new(config)); }
+        }
+        // 模型或分词器配置可能已变化
+        tokenizer::reset();
+        Ok(())
+    }
+
+    /// 合并结果及各值来源，密钥打码
+    pub fn describe(&self) -> String {
+        self.layered.describe()
+    }
+
+    /// 加载并合并各层配置；用户配置不存在时引导用户创建
     pub fn load_or_init() -> Result<Self, String> {
         let mut path = get_home_path()?;
         path.push(CONFIG_FILENAME);
 
         if path.exists() {
-            let content = match fs::read_to_string(&path) {
-                Err(e) => { return Err(format!("无法读取配置文件 <{}>: {}", &path.to_string_lossy(), e)) }
-                Ok(c) => c
-            };
-
-            match serde_json::from_str(&content) {
-                Err(e) =>
-                    Err(format!("配置文件 JSON 解析错误 <{}>: {}", &path.to_string_lossy(), e)),
-                Ok(c) => Ok(c)
+            let mut warnings = Vec::new();
+            let mut layered = Layered::new();
+            layered.merge(
+                serde_json::json!({ "theme": DEFAULT_THEME, "context_window": DEFAULT_CONTEXT_WINDOW, "keep_reasoning": false, "tool_policy": "deny" }),
+                "默认值",
+            );
+            check_permissions(&path, &mut warnings);
+            let mut user = Self::read_layer(&path)?;
+            if is_legacy(&user) {
+                user = Self::from_legacy(&path, user)?;
+                warnings.push(format!(
+                    "用户配置 <{}> 为旧版格式，已按 providers 格式载入；运行 `oxicodent config migrate` 写回新格式",
+                    path.to_string_lossy(),
+                ));
             }
-        } else {
-            let config = Config {
-                api_key: "".into(),
-                api_base: "http://127.0.0.1:11434/v1/chat/completions".into(),
-                melchior_model: "qwen3-14b-32k:latest".into(),
-                casper_model: "qwen2.5-coder-14b-32k:latest".into(),
-                balthazar_model: "qwen3-4b-32k-instruct:latest".into()
-            };
-
-            let json = serde_json::to_string_pretty(&config)
-                .expect("Config 结构体 -> JSON 转换错误");
-
-            if let Err(e) = fs::write(&path, json) {
-                return Err(format!("无法写入文件 <{}>: {}", &path.to_string_lossy(), e))
+            layered.merge(user, &format!("用户配置 {}", path.to_string_lossy()));
+
+            // 项目配置只读取，不主动创建工作区目录；其随仓库分发，不检查权限
+            let project_path = PathBuf::from(ROOT_DIR).join(CONFIG_FILENAME);
+            let project_source = format!("项目配置 {}", project_path.to_string_lossy());
+            if project_path.exists() {
+                let mut project = Self::read_layer(&project_path)?;
+                sanitize_project_layer(&mut project, &mut warnings);
+                layered.merge(project, &project_source);
             }
 
+            let env = Overrides::from_env()?.layer(&layered.value);
+            layered.merge(env, "环境变量");
+            if let Some(cli) = cli_overrides() {
+                let cli = cli.layer(&layered.value);
+                layered.merge(cli, "命令行参数");
+            }
+
+            let mut config: Config = serde_json::from_value(layered.value.clone())
+                .map_err(|e| format!("配置解析错误: {}", e))?;
+            config.validate().map_err(|e| format!("配置错误: {}", e))?;
+            config.check_project_endpoints(&layered, &project_source)?;
+            // api_key_env / api_key_cmd 在角色首次请求时才解析，这里只登记明文密钥
+            register_secrets(config.providers.iter().map(|p| p.api_key.clone()));
+
+            config.layered = layered;
+            config.warnings = warnings;
+            Ok(config)
+        } else {
+            let provider = Provider::new(
+                "ollama",
+                ProviderKind::Ollama,
+                "http://127.0.0.1:11434/v1/chat/completions".into(),
+                "".into(),
+            );
+            let config = Config::template(
+                provider,
+                "qwen3-14b-32k:latest",
+                "qwen2.5-coder-14b-32k:latest",
+                "qwen3-4b-32k-instruct:latest",
+            );
+            config.save()?;
+
             Err(format!("已在 ~/{}/{} 创建模板，请配置后重启。", ROOT_DIR, CONFIG_FILENAME))
         }
     }
+
+    /// 用户配置文件是否存在
+    pub fn exists() -> Result<bool, String> {
+        Ok(get_home_path()?.join(CONFIG_FILENAME).exists())
+    }
+
+    /// 单 provider 的初始配置，CASPER-I 与 CASPER-II 使用同一模型
+    pub fn template(provider: Provider, melchior: &str, casper: &str, balthazar: &str) -> Self {
+        let role = |model: &str| RoleConfig::new(&provider.name, model);
+        Config {
+            // MELCHIOR 偏发散，CASPER-II 生成补丁需确定性输出
+            roles: Roles {
+                melchior: RoleConfig { temperature: Some(0.8), ..role(melchior) },
+                casper_i: role(casper),
+                casper_ii: RoleConfig { temperature: Some(0.0), ..role(casper) },
+                balthazar: role(balthazar),
+            },
+            providers: vec![provider],
+            theme: default_theme(),
+            context_window: default_context_window(),
+            keep_reasoning: false,
+            tool_policy: ToolPolicy::default(),
+            prices: BTreeMap::new(),
+            tokenizers: BTreeMap::new(),
+            layered: Layered::new(),
+            warnings: Vec::new(),
+        }
+    }
+
+    /// 写入用户配置文件（仅所有者可读写）
+    pub fn save(&self) -> Result<(), String> {
+        let path = get_home_path()?.join(CONFIG_FILENAME);
+        let json = serde_json::to_string_pretty(self)
+            .expect("Config 结构体 -> JSON 转换错误");
+        write_private(&path, &json)
+    }
+
+    /// 读取单个配置文件，不做任何转换
+    fn read_layer(path: &PathBuf) -> Result<serde_json::Value, String> {
+        let content = fs::read_to_string(path)
+            .map_err(|e| format!("无法读取配置文件 <{}>: {}", &path.to_string_lossy(), e))?;
+
+        serde_json::from_str(&content)
+            .map_err(|e| format!("配置文件 JSON 解析错误 <{}>: {}", &path.to_string_lossy(), e))
+    }
+
+    /*
+     * -------- [ 旧版配置迁移 ] --------
+     * 只针对用户配置 ~/.oxicodent/config.json：
+     * - 载入时在内存中转换为 providers 格式，不改动文件
+     * - `oxicodent config migrate` 显式写回，原文件备份为 config.json.bak
+     */
+    fn from_legacy(path: &Path, value: serde_json::Value) -> Result<serde_json::Value, String> {
+        let legacy: LegacyConfig = serde_json::from_value(value)
+            .map_err(|e| format!("旧版配置解析错误 <{}>: {}", &path.to_string_lossy(), e))?;
+        Ok(serde_json::to_value(Config::from(legacy)).expect("Config 结构体 -> JSON 转换错误"))
+    }
+
+    /// 将旧版用户配置写回为 providers 格式，返回结果说明
+    pub fn migrate() -> Result<String, String> {
+        let path = get_home_path()?.join(CONFIG_FILENAME);
+        let value = Self::read_layer(&path)?;
+        if !is_legacy(&value) {
+            return Ok(format!("用户配置 <{}> 已是 providers 格式，无需迁移", path.to_string_lossy()))
+        }
+        let json = serde_json::to_string_pretty(&Self::from_legacy(&path, value)?)
+            .expect("Config 结构体 -> JSON 转换错误");
+
+        let backup = path.with_extension("json.bak");
+        let original = fs::read_to_string(&path)
+            .map_err(|e| format!("无法读取配置文件 <{}>: {}", &path.to_string_lossy(), e))?;
+        write_private(&backup, &original)?;
+        write_private(&path, &json)?;
+
+        info!("已将旧版配置迁移至 providers 格式，原文件备份于 <{}>", backup.to_string_lossy());
+        Ok(format!("已将 <{}> 迁移至 providers 格式，原文件备份于 <{}>", path.to_string_lossy(), backup.to_string_lossy()))
+    }
+}
+
+/// 旧版扁平格式：顶层有 api_base 而没有 providers
+fn is_legacy(value: &serde_json::Value) -> bool {
+    value.get("providers").is_none() && value.get("api_base").is_some()
+}
+
+/*
+ * -------- [ 配置文件权限 ] --------
+ * 配置中可能含有 API Key：写入时设为 0600；载入用户配置时发现组或其他用户可访问，收紧为 0600 并提示
+ */
+pub fn write_private(path: &Path, content: &str) -> Result<(), String> {
+    // 新建时即为 0600；已存在的文件先收紧权限再写入
+    let mut file = OpenOptions::new().write(true).create(true).truncate(true).mode(0o600).open(path)
+        .map_err(|e| format!("无法写入文件 <{}>: {}", &path.to_string_lossy(), e))?;
+    file.set_permissions(fs::Permissions::from_mode(0o600))
+        .map_err(|e| format!("无法设置文件权限 <{}>: {}", &path.to_string_lossy(), e))?;
+    file.write_all(content.as_bytes())
+        .map_err(|e| format!("无法写入文件 <{}>: {}", &path.to_string_lossy(), e))
+}
+
+fn check_permissions(path: &Path, warnings: &mut Vec<String>) {
+    let Ok(metadata) = fs::metadata(path) else { return };
+    let mode = metadata.permissions().mode() & 0o777;
+    if mode & 0o077 == 0 {
+        return
+    }
+
+    let warning = match fs::set_permissions(path, fs::Permissions::from_mode(0o600)) {
+        Ok(_) => format!("配置文件 <{}> 权限为 {:o}，其他用户可读取，已收紧为 600", path.to_string_lossy(), mode),
+        Err(e) => format!("配置文件 <{}> 权限为 {:o}，其他用户可读取，且无法修改: {}", path.to_string_lossy(), mode, e),
+    };
+    warn!("{}", warning);
+    warnings.push(warning);
+}
+
+/*
+ * -------- [ 项目配置限制 ] --------
+ * 项目配置随仓库分发，不可信：
+ * - 不允许 api_key_env / api_key_cmd，避免打开仓库即执行其中的命令或读取任意环境变量
+ * - 带有密钥（无论来自哪一层）的 provider，其 api_base 不能来自项目配置，避免密钥被发往其他地址
+ * 这些字段只能来自用户配置、环境变量或命令行参数
+ */
+fn sanitize_project_layer(layer: &mut serde_json::Value, warnings: &mut Vec<String>) {
+    // 旧版扁平字段只在用户配置中迁移
+    if is_legacy(layer) && let Some(fields) = layer.as_object_mut() {
+        for field in ["api_key", "api_base", "melchior_model", "casper_model", "balthazar_model"] {
+            fields.remove(field);
+        }
+        let warning = "项目配置为旧版格式，不会迁移；其中的 api_base、api_key 与 *_model 已忽略".to_string();
+        warn!("{}", warning);
+        warnings.push(warning);
+    }
+
+    let Some(providers) = layer.get_mut("providers").and_then(|p| p.as_array_mut()) else { return };
+    for provider in providers.iter_mut().filter_map(|p| p.as_object_mut()) {
+        let name = provider.get("name").and_then(|n| n.as_str()).unwrap_or_default().to_string();
+        for field in ["api_key_env", "api_key_cmd"] {
+            if provider.remove(field).is_some() {
+                let warning = format!("项目配置不允许设置 provider <{}> 的 {}，已忽略", name, field);
+                warn!("{}", warning);
+                warnings.push(warning);
+            }
+        }
+    }
 }
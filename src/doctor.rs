@@ -0,0 +1,141 @@
+use std::time::{Duration, Instant};
+use crate::api_client::{list_models, ApiClient};
+use crate::app::Model;
+use crate::config_manager::{get_config, service_root, ProviderKind};
+
+// 模型不存在时最多列出的可用模型数
+const MAX_LISTED_MODELS: usize = 10;
+
+/// 待检查的 provider 及使用它的角色
+struct Target {
+    name: String,
+    kind: ProviderKind,
+    api_base: String,
+    // 未被角色使用的 provider 不解析 api_key_env / api_key_cmd
+    api_key: Result<String, String>,
+    roles: Vec<(Model, String)>,
+}
+
+/*
+ * -------- [ 连接诊断 ] --------
+ * 供 `oxicodent doctor` 与 `/doctor` 使用，逐个 provider：
+ * - 请求模型列表，确认服务可达并测量延迟
+ * - 检查各角色配置的模型名是否存在于服务端
+ * - 以极短的提示词发起流式请求，测量首 token 时间并确认流式输出正常
+ * 返回诊断报告与是否全部通过
+ */
+pub fn run(client: &ApiClient) -> (String, bool) {
+    let targets = targets();
+    let mut report = String::new();
+    let mut problems = 0;
+
+    for target in &targets {
+        report.push_str(&format!("provider <{}> · {} · {}\n", target.name, target.kind.label(), target.api_base));
+
+        let api_key = match &target.api_key {
+            Ok(key) => key,
+            Err(e) => {
+                problems += 1;
+                report.push_str(&format!("  ✗ 无法获取密钥: {}\n", e));
+                continue
+            }
+        };
+
+        // --- [ 模型列表 ] ---
+        let root = service_root(&target.api_base);
+        let start = Instant::now();
+        let models = match list_models(target.kind, &root, api_key) {
+            Ok(models) => {
+                report.push_str(&format!("  ✓ 服务可达，延迟 {}，共 {} 个模型\n", format_duration(start.elapsed()), models.len()));
+                Some(models)
+            }
+            Err(e) => {
+                problems += 1;
+                report.push_str(&format!("  ✗ 无法获取模型列表: {}\n", e));
+                None
+            }
+        };
+
+        if target.roles.is_empty() {
+            report.push_str("  - 未被任何角色使用\n");
+        }
+
+        // --- [ 角色检查 ] ---
+        for (model, name) in &target.roles {
+            let label = format!("{:<10} {}", model.name(), name);
+
+            if let Some(models) = &models
+                && !models.contains(name) {
+                problems += 1;
+                report.push_str(&format!("  ✗ {}: 服务端不存在该模型\n", label));
+                report.push_str(&format!("    可用模型: {}\n", preview_models(models)));
+                continue
+            }
+
+            match client.probe(*model) {
+                Ok(probe) if probe.events == 0 => {
+                    problems += 1;
+                    report.push_str(&format!("  ✗ {}: 未收到流式事件，服务端可能不支持流式输出\n", label));
+                }
+                Ok(probe) => {
+                    let first_token = probe.first_token.map_or("无文本输出".to_string(), |t| format!("首 token {}", format_duration(t)));
+                    let mut line = format!("  ✓ {}: {} · 总耗时 {} · {} 个流式事件", label, first_token, format_duration(probe.total), probe.events);
+                    if !probe.done {
+                        line.push_str(" · 未收到结束标记");
+                    }
+                    report.push_str(&line);
+                    report.push('\n');
+                }
+                Err(e) => {
+                    problems += 1;
+                    report.push_str(&format!("  ✗ {}: {}\n", label, e));
+                }
+            }
+        }
+    }
+
+    if problems == 0 {
+        report.push_str("全部检查通过\n");
+    } else {
+        report.push_str(&format!("发现 {} 个问题\n", problems));
+    }
+    (report, problems == 0)
+}
+
+/// 从当前配置收集检查目标，避免在网络请求期间持有配置锁
+fn targets() -> Vec<Target> {
+    let config = get_config().read().unwrap();
+    config.providers.iter().map(|provider| Target {
+        name: provider.name.clone(),
+        kind: provider.kind,
+        api_base: provider.api_base.clone(),
+        api_key: if Model::ALL.iter().any(|model| config.role(model).provider == provider.name) {
+            provider.key()
+        } else {
+            Ok(provider.api_key.clone())
+        },
+        roles: Model::ALL.iter()
+            .filter(|model| config.role(model).provider == provider.name)
+            .map(|model| (*model, config.model_name(model).to_string()))
+            .collect(),
+    }).collect()
+}
+
+fn preview_models(models: &[String]) -> String {
+    if models.is_empty() {
+        return "（无）".into()
+    }
+    let mut preview = models.iter().take(MAX_LISTED_MODELS).cloned().collect::<Vec<_>>().join(", ");
+    if models.len() > MAX_LISTED_MODELS {
+        preview.push_str(&format!(" 等 {} 个", models.len()));
+    }
+    preview
+}
+
+fn format_duration(duration: Duration) -> String {
+    if duration < Duration::from_secs(1) {
+        format!("{}ms", duration.as_millis())
+    } else {
+        format!("{:.2}s", duration.as_secs_f64())
+    }
+}
@@ -1,12 +1,16 @@
 use std::time::Duration;
 use crate::ui::Ui;
 use crate::io_thread::IOThread;
-use crate::app::{AppMessage, PendingAction, SystemMessage};
+use crate::app::{AppMessage, Model, PendingAction, SystemMessage};
 use crate::worker_thread::WorkerThread;
+use crate::command::{self, CommandContext, SlashCommand};
+use crate::transcript::EntryKind;
 use crossterm::{
-    event::{self, Event, KeyCode},
+    event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers, MouseEventKind},
 };
 
+const MOUSE_SCROLL_ROWS: usize = 3;
+
 /*
  * -------- [ 键盘事件监听 ] --------
  * - 负责修改处理输入
@@ -14,56 +18,245 @@ use crossterm::{
  * - 返回是否 退出
  */
 pub fn handle_event(ui: &mut Ui, io_thread: &mut IOThread, worker_thread: &mut WorkerThread) -> Result<bool, Box<dyn std::error::Error>> {
-    if event::poll(Duration::from_millis(10))? {
-        if let Event::Key(key) = event::read()? {
-            match key.code {
-                KeyCode::Char('u') if key.modifiers.contains(event::KeyModifiers::CONTROL) => {
-                    ui.scroll_offset = ui.scroll_offset.saturating_sub(5);
-                    ui.is_auto_scroll = false;
-                }
-                KeyCode::Char('d') if key.modifiers.contains(event::KeyModifiers::CONTROL) => {
-                    ui.scroll_offset = ui.scroll_offset.saturating_add(5);
-                    ui.is_auto_scroll = false;
-                }
+    if !event::poll(Duration::from_millis(10))? {
+        return Ok(false)
+    }
+
+    match event::read()? {
+        Event::Key(key) if key.kind != KeyEventKind::Release =>
+            handle_key(key, ui, io_thread, worker_thread),
+        // 粘贴内容整体插入，不会触发发送
+        Event::Mouse(mouse) => {
+            match mouse.kind {
+                MouseEventKind::ScrollUp => ui.tab_mut().scroll.scroll_up(MOUSE_SCROLL_ROWS),
+                MouseEventKind::ScrollDown => ui.tab_mut().scroll.scroll_down(MOUSE_SCROLL_ROWS),
+                _ => {}
+            }
+            Ok(false)
+        }
+        Event::Paste(text) => {
+            let tab = ui.tab_mut();
+            if let PendingAction::None = &tab.pending_action {
+                tab.input.insert_str(&text);
+            }
+            Ok(false)
+        }
+        _ => Ok(false)
+    }
+}
+
+fn handle_key(key: KeyEvent, ui: &mut Ui, io_thread: &mut IOThread, worker_thread: &mut WorkerThread) -> Result<bool, Box<dyn std::error::Error>> {
+    let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+    let alt = key.modifiers.contains(KeyModifiers::ALT);
+    let shift = key.modifiers.contains(KeyModifiers::SHIFT);
+    let editing = matches!(ui.tab().pending_action, PendingAction::None);
 
-                KeyCode::Esc => return Ok(true),
-                KeyCode::Enter => {
-                    if let PendingAction::None = &ui.pending_action {
-                        let query = std::mem::take(&mut ui.input);
-                        ui.history_display.push_str(&format!("\nUSER: {}\n", query));
-                        io_thread.send(AppMessage::UserQuery(query));
+    match key.code {
+        // --- [ 标签页切换 ] ---
+        // F1-F4 或 Ctrl-1..4；部分终端不上报 Ctrl+数字，Alt-1..4 作为后备
+        KeyCode::F(n @ 1..=4) => ui.switch_tab(Model::ALL[n as usize - 1]),
+        KeyCode::Char(c @ '1'..='4') if ctrl || alt =>
+            ui.switch_tab(Model::ALL[c as usize - '1' as usize]),
+
+        // --- [ 对话区滚动 ] ---
+        KeyCode::Char('u') if ctrl => ui.tab_mut().scroll.scroll_up(5),
+        KeyCode::Char('d') if ctrl => ui.tab_mut().scroll.scroll_down(5),
+        KeyCode::PageUp => ui.tab_mut().scroll.page_up(),
+        KeyCode::PageDown => ui.tab_mut().scroll.page_down(),
+        // 输入框为空时 Home/End 直接作用于对话区
+        KeyCode::Home if ctrl || ui.tab().input.is_empty() => ui.tab_mut().scroll.scroll_to_top(),
+        KeyCode::End if ctrl || ui.tab().input.is_empty() => ui.tab_mut().scroll.scroll_to_bottom(),
+
+        // --- [ 工具块 / 思考块 ] ---
+        // Alt+↑/↓ 选择工具块或思考块，Ctrl+O 展开/折叠（未选中时作用于最后一个）
+        KeyCode::Up if alt => ui.tab_mut().select_prev_block(),
+        KeyCode::Down if alt => ui.tab_mut().select_next_block(),
+        KeyCode::Char('o') if ctrl => ui.tab_mut().toggle_block(),
+
+        // 填写拒绝理由时，ESC 返回确认弹窗；选中工具块时，ESC 取消选择
+        KeyCode::Esc => {
+            let tab = ui.tab_mut();
+            if let PendingAction::RejectReason(action, _) = &tab.pending_action {
+                tab.pending_action = (**action).clone();
+            } else if tab.transcript.selected().is_some() {
+                tab.transcript.clear_selection();
+            } else {
+                return Ok(true)
+            }
+        }
+        // Shift+Enter / Alt+Enter 换行
+        KeyCode::Enter if editing && (shift || alt) => ui.tab_mut().input.newline(),
+        KeyCode::Enter => {
+            match &ui.tab().pending_action {
+                PendingAction::None => {
+                    if ui.tab().input.is_empty() { return Ok(false) }
+                    let query = ui.tab_mut().input.submit();
+                    if let Some((cmd, args, raw_args)) = parse_command(&query) {
+                        run_command(cmd, &args, raw_args, ui, io_thread);
+                    } else {
+                        let query = literal_query(query);
+                        ui.push(EntryKind::User(query.clone()));
+                        io_thread.send(AppMessage::UserQuery(ui.tab().model, query));
                     }
                 }
-                KeyCode::Char(c) => {
-                    match &ui.pending_action {
-                        PendingAction::None => ui.input.push(c),
-                        PendingAction::ConfirmExec(exec) => {
-                            if c == 'y' || c == 'Y' {
-                                worker_thread.send(AppMessage::SysMsg(SystemMessage::ExecCommand(exec.to_string())));
-                                ui.pending_action = PendingAction::None;
-                            } else if c == 'n' || c == 'N' {
-                                ui.pending_action = PendingAction::None;
-                            }
-                        }
-                        PendingAction::ConfirmDiff(file_path, diff) => {
-                            if c == 'y' || c == 'Y' {
-                                worker_thread.send(AppMessage::SysMsg(SystemMessage::Diff(file_path.to_string(), diff.to_string())));
-                                ui.pending_action = PendingAction::None;
-                            } else if c == 'n' || c == 'N' {
-                                ui.pending_action = PendingAction::None;
-                            }
-                        }
+                PendingAction::RejectReason(_, reason) => {
+                    reject(ui, io_thread, reason.trim().to_string());
+                }
+                _ => {}
+            }
+        }
+        // --- [ 输入编辑 ] ---
+        KeyCode::Tab if editing && ui.tab().input.text().starts_with('/') => {
+            let (completed, candidates) = command::complete(ui.tab().input.text());
+            ui.tab_mut().input.set_text(completed);
+            if !candidates.is_empty() {
+                ui.push_notice(&candidates.join("  "));
+            }
+        }
+        KeyCode::Left if editing && (ctrl || alt) => ui.tab_mut().input.move_word_left(),
+        KeyCode::Right if editing && (ctrl || alt) => ui.tab_mut().input.move_word_right(),
+        KeyCode::Left if editing => ui.tab_mut().input.move_left(),
+        KeyCode::Right if editing => ui.tab_mut().input.move_right(),
+        KeyCode::Up if editing => ui.tab_mut().input.move_up(),
+        KeyCode::Down if editing => ui.tab_mut().input.move_down(),
+        KeyCode::Home if editing => ui.tab_mut().input.move_home(),
+        KeyCode::End if editing => ui.tab_mut().input.move_end(),
+        KeyCode::Delete if editing => ui.tab_mut().input.delete(),
+        KeyCode::Char('a') if editing && ctrl => ui.tab_mut().input.move_home(),
+        KeyCode::Char('e') if editing && ctrl => ui.tab_mut().input.move_end(),
+        KeyCode::Char('w') if editing && ctrl => ui.tab_mut().input.delete_word_left(),
+        KeyCode::Char('b') if editing && alt => ui.tab_mut().input.move_word_left(),
+        KeyCode::Char('f') if editing && alt => ui.tab_mut().input.move_word_right(),
+
+        KeyCode::Char(c) => {
+            let model = ui.tab().model;
+            match &mut ui.tab_mut().pending_action {
+                PendingAction::None => ui.tab_mut().input.insert_char(c),
+                PendingAction::ConfirmExec(exec) => {
+                    if c == 'y' || c == 'Y' {
+                        let exec = exec.to_string();
+                        ui.push(EntryKind::ToolCall { tool: "exec", detail: exec.clone(), result: None });
+                        worker_thread.send(AppMessage::SysMsg(SystemMessage::ExecCommand(model, exec)));
+                        ui.tab_mut().pending_action = PendingAction::None;
+                    } else if c == 'n' || c == 'N' {
+                        start_reject(ui);
                     }
                 }
-                KeyCode::Backspace => {
-                    if let PendingAction::None = &ui.pending_action {
-                        ui.input.pop();
+                PendingAction::ConfirmDiff(file_path, diff) => {
+                    if c == 'y' || c == 'Y' {
+                        let (file_path, diff) = (file_path.to_string(), diff.to_string());
+                        ui.push(EntryKind::ToolCall { tool: "diff", detail: file_path.clone(), result: None });
+                        worker_thread.send(AppMessage::SysMsg(SystemMessage::Diff(model, file_path, diff)));
+                        ui.tab_mut().pending_action = PendingAction::None;
+                    } else if c == 'n' || c == 'N' {
+                        start_reject(ui);
                     }
                 }
+                PendingAction::RejectReason(_, reason) => reason.push(c)
+            }
+        }
+        KeyCode::Backspace => {
+            match &mut ui.tab_mut().pending_action {
+                PendingAction::None if ctrl || alt => ui.tab_mut().input.delete_word_left(),
+                PendingAction::None => ui.tab_mut().input.backspace(),
+                PendingAction::RejectReason(_, reason) => { reason.pop(); }
                 _ => {}
             }
         }
+        _ => {}
     }
 
     Ok(false)
-}
\ No newline at end of file
+}
+
+/*
+ * -------- [ 斜杠命令解析 ] --------
+ * 只有命令名已注册时才视为命令，返回命令、参数与命令名之后的原文；
+ * 其余输入（如 `/etc/nginx/nginx.conf 为什么报错`）作为普通问题发送给模型
+ */
+fn parse_command(input: &str) -> Option<(&'static SlashCommand, Vec<&str>, &str)> {
+    let body = input.trim().strip_prefix('/')?.trim_start();
+    let name = body.split_whitespace().next()?;
+    let cmd = command::find_command(name)?;
+    let raw_args = body[name.len()..].trim_start();
+    Some((cmd, raw_args.split_whitespace().collect(), raw_args))
+}
+
+/// 以 `//` 开头的输入去掉一个 `/` 后原样发送，用于发送与命令同名的文本
+fn literal_query(input: String) -> String {
+    match input.trim_start().strip_prefix("//") {
+        Some(rest) => format!("/{}", rest),
+        None => input,
+    }
+}
+
+/// 执行斜杠命令，用法错误显示在对话区
+fn run_command(cmd: &SlashCommand, args: &[&str], raw_args: &str, ui: &mut Ui, io_thread: &mut IOThread) {
+    let mut ctx = CommandContext { ui, io_thread, raw_args };
+    if let Err(e) = (cmd.run)(args, &mut ctx) {
+        ctx.ui.push_error(&format!("{}\n用法: {}", e, cmd.usage));
+    }
+}
+
+/// 进入拒绝理由输入状态
+fn start_reject(ui: &mut Ui) {
+    let tab = ui.tab_mut();
+    let action = std::mem::replace(&mut tab.pending_action, PendingAction::None);
+    tab.pending_action = PendingAction::RejectReason(Box::new(action), String::new());
+}
+
+/*
+ * -------- [ 拒绝工具调用 ] --------
+ * 将被拒绝的操作与理由（可为空）以 system 消息反馈给当前标签页的模型，避免对话停滞
+ */
+fn reject(ui: &mut Ui, io_thread: &mut IOThread, reason: String) {
+    let tab = ui.tab_mut();
+    let action = tab.pending_action.describe();
+    let feedback = if reason.is_empty() {
+        format!("System: User rejected {}", action)
+    } else {
+        format!("System: User rejected {}: {}", action, reason)
+    };
+
+    tab.push(EntryKind::Rejected(feedback.clone()));
+    tab.pending_action = PendingAction::None;
+    io_thread.send(AppMessage::SysMsg(SystemMessage::Rejected(tab.model, feedback)));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn registered_commands_are_parsed() {
+        let (cmd, args, raw_args) = parse_command("  /pin  第一行\n  第二行 ").unwrap();
+        assert_eq!(cmd.name, "pin");
+        assert_eq!(args, ["第一行", "第二行"]);
+        assert_eq!(raw_args, "第一行\n  第二行");
+
+        let (cmd, args, _) = parse_command("/model casper-i").unwrap();
+        assert_eq!(cmd.name, "model");
+        assert_eq!(args, ["casper-i"]);
+    }
+
+    #[test]
+    fn path_like_input_is_a_query() {
+        assert!(parse_command("/etc/nginx/nginx.conf 为什么报错").is_none());
+        assert!(parse_command("/usr/bin/env").is_none());
+    }
+
+    #[test]
+    fn unknown_word_is_a_query() {
+        assert!(parse_command("/frobnicate 一下").is_none());
+        assert!(parse_command("/").is_none());
+        assert!(parse_command("普通问题 /model").is_none());
+    }
+
+    #[test]
+    fn double_slash_sends_rest_literally() {
+        assert!(parse_command("//model casper-i").is_none());
+        assert_eq!(literal_query("//model casper-i".into()), "/model casper-i");
+        assert_eq!(literal_query("/etc/hosts 是什么".into()), "/etc/hosts 是什么");
+        assert_eq!(literal_query("普通问题".into()), "普通问题");
+    }
+}
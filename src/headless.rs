@@ -0,0 +1,182 @@
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::Path;
+use std::sync::mpsc;
+use std::thread;
+use crate::api_client::ApiClient;
+use crate::app::*;
+use crate::compact::compact;
+use crate::config_manager::{get_config, ToolPolicy};
+use crate::io_thread::{initial_history, invalidate_reads, read_context};
+use crate::provider::with_reasoning;
+use crate::redact::redact;
+use crate::worker_thread::{parse_tool_call, run_diff, run_exec, run_read};
+
+// 单次运行的请求轮数上限，避免工具调用无限循环
+const MAX_ROUNDS: usize = 32;
+
+// 退出码
+const EXIT_OK: i32 = 0;
+const EXIT_ERROR: i32 = 1;
+const EXIT_REJECTED: i32 = 2;
+const EXIT_TOOL_FAILED: i32 = 3;
+
+/*
+ * -------- [ 非交互模式 ] --------
+ * `oxicodent ask` 与 `oxicodent run --spec`，供脚本、Makefile 与 git hook 调用：
+ * - 模型回复流式输出到标准输出，工具调用与提示信息输出到标准错误
+ * - 工具调用按 tool_policy（或 --yes）处理，结果写回上下文后继续请求，直到回复中不再有工具调用
+ * - 退出码：0 完成；1 请求失败或超过轮数上限；2 有工具调用被策略拒绝；
+ *   3 有补丁应用失败或命令以非零状态退出（同时有拒绝时以 3 为准）；读取失败属于正常探索，不计入
+ */
+pub fn ask(role: Model, question: Option<String>, yes: bool) -> i32 {
+    // 省略问题时从标准输入读取，便于管道调用
+    let question = match question {
+        Some(question) => question,
+        None => {
+            let mut input = String::new();
+            if let Err(e) = io::stdin().read_to_string(&mut input) {
+                eprintln!("无法读取标准输入: {}", e);
+                return EXIT_ERROR
+            }
+            input
+        }
+    };
+    if question.trim().is_empty() {
+        eprintln!("问题不能为空");
+        return EXIT_ERROR
+    }
+    run(role, question, yes)
+}
+
+pub fn run_spec(role: Model, path: &Path, yes: bool) -> i32 {
+    let spec = match fs::read_to_string(path) {
+        Ok(spec) => spec,
+        Err(e) => {
+            eprintln!("无法读取规格书 <{}>: {}", path.to_string_lossy(), e);
+            return EXIT_ERROR
+        }
+    };
+    // 以 ```spec 块发送，上下文压缩时保留
+    let query = format!("请按以下规格书完成修改：\n```spec\n{}\n```", spec.trim_end());
+    run(role, query, yes)
+}
+
+fn run(role: Model, query: String, yes: bool) -> i32 {
+    let client = match ApiClient::new() {
+        Ok(client) => client,
+        Err(e) => {
+            eprintln!("{}", e);
+            return EXIT_ERROR
+        }
+    };
+    let policy = if yes { ToolPolicy::Auto } else { get_config().read().unwrap().tool_policy };
+
+    let mut messages = initial_history(&role);
+    messages.push(ChatMessage { role: "user".into(), content: redact(&query) });
+    let mut rejected = false;
+    let mut failed = false;
+
+    for _ in 0..MAX_ROUNDS {
+        compact(role, &mut messages, 0, &client, &|msg| eprintln!("{}", msg));
+        let reply = match stream(&client, role, messages.clone()) {
+            Ok(reply) => reply,
+            Err(e) => {
+                eprintln!("{}", e);
+                return EXIT_ERROR
+            }
+        };
+        messages.push(ChatMessage { role: "assistant".into(), content: reply.context });
+
+        let Some(call) = parse_tool_call(reply.content) else {
+            return if failed {
+                EXIT_TOOL_FAILED
+            } else if rejected {
+                EXIT_REJECTED
+            } else {
+                EXIT_OK
+            }
+        };
+        let result = match call.tool {
+            Tool::Read => {
+                let output = run_read(&call.content);
+                report("read", &call.content, &output);
+                if output.success { read_context(&call.content, &output.content) } else { output.content }
+            }
+            Tool::Exec if policy == ToolPolicy::Auto => {
+                let output = run_exec(&call.content);
+                report("exec", call.content.trim(), &output);
+                failed |= !output.success;
+                output.content
+            }
+            Tool::Diff(file_path) if policy != ToolPolicy::Deny => {
+                let output = run_diff(&file_path, &call.content);
+                report("diff", &file_path, &output);
+                if output.success {
+                    invalidate_reads(&mut messages, &file_path);
+                }
+                failed |= !output.success;
+                output.content
+            }
+            tool => {
+                let action = match tool {
+                    Tool::Diff(file_path) => PendingAction::ConfirmDiff(file_path, call.content),
+                    _ => PendingAction::ConfirmExec(call.content),
+                };
+                rejected = true;
+                let feedback = format!("System: User rejected {}: 非交互模式下 tool_policy 为 {}，未执行", action.describe(), policy.name());
+                eprintln!("[拒绝] {}（可使用 --yes 或调整 tool_policy）", action.describe());
+                feedback
+            }
+        };
+        messages.push(ChatMessage { role: "system".into(), content: redact(&result) });
+    }
+
+    eprintln!("超过 {} 轮请求仍未完成，已停止", MAX_ROUNDS);
+    EXIT_ERROR
+}
+
+/// 一次回复：content 为正文，context 为写入上下文的内容（按 keep_reasoning 附带思考过程）
+struct Reply {
+    content: String,
+    context: String,
+}
+
+/// 流式请求并将正文实时写到标准输出；思考过程不输出
+fn stream(client: &ApiClient, role: Model, messages: Vec<ChatMessage>) -> Result<Reply, String> {
+    let (tx, rx) = mpsc::channel();
+    let mut content = String::new();
+    let mut reasoning = String::new();
+    let mut error = None;
+
+    thread::scope(|scope| {
+        scope.spawn(move || client.send_chat_stream(role, messages, tx));
+
+        let mut stdout = io::stdout();
+        for msg in rx {
+            match msg {
+                AppMessage::AIMsg(AssistantMessage::ModelChunk(_, chunk)) => {
+                    let _ = write!(stdout, "{}", chunk).and_then(|_| stdout.flush());
+                    content.push_str(&chunk);
+                }
+                AppMessage::AIMsg(AssistantMessage::ReasoningChunk(_, chunk)) => reasoning.push_str(&chunk),
+                AppMessage::SysMsg(SystemMessage::SystemLog(e)) => error = Some(e),
+                _ => {}
+            }
+        }
+        if !content.is_empty() && !content.ends_with('\n') {
+            let _ = writeln!(stdout);
+        }
+    });
+
+    if let Some(e) = error {
+        return Err(e)
+    }
+    let context = with_reasoning(&content, &reasoning);
+    Ok(Reply { content, context })
+}
+
+/// 工具调用的结果摘要，输出到标准错误
+fn report(tool: &str, detail: &str, output: &ToolOutput) {
+    eprintln!("[{}] {} · {} · {:.1}s", tool, detail, output.status, output.elapsed.as_secs_f64());
+}
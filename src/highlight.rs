@@ -0,0 +1,164 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::path::Path;
+use std::sync::OnceLock;
+use std::sync::atomic::{AtomicBool, Ordering};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::Span;
+use syntect::easy::HighlightLines;
+use syntect::highlighting::{FontStyle, Theme, ThemeSet};
+use syntect::parsing::{SyntaxReference, SyntaxSet};
+use tracing::warn;
+use crate::config_manager::get_config;
+
+const CACHE_LIMIT: usize = 256;
+
+/// 一行高亮结果
+pub type StyledLine = Vec<Span<'static>>;
+
+struct Assets {
+    syntaxes: SyntaxSet,
+    themes: ThemeSet,
+}
+
+/// 内置语法与主题（编译期打包，离线可用），首次使用时加载
+fn assets() -> &'static Assets {
+    static ASSETS: OnceLock<Assets> = OnceLock::new();
+    ASSETS.get_or_init(|| Assets {
+        syntaxes: SyntaxSet::load_defaults_newlines(),
+        themes: ThemeSet::load_defaults(),
+    })
+}
+
+/// 高亮结果缓存，超出容量时淘汰最久未使用的一项
+#[derive(Default)]
+struct Cache {
+    // hash -> (最近使用序号, 高亮结果)
+    entries: HashMap<u64, (u64, Vec<StyledLine>)>,
+    clock: u64,
+}
+
+impl Cache {
+    fn get(&mut self, hash: u64) -> Option<Vec<StyledLine>> {
+        self.clock += 1;
+        let (used, lines) = self.entries.get_mut(&hash)?;
+        *used = self.clock;
+        Some(lines.clone())
+    }
+
+    fn insert(&mut self, hash: u64, lines: Vec<StyledLine>) {
+        if self.entries.len() >= CACHE_LIMIT
+            && let Some(oldest) = self.entries.iter().min_by_key(|(_, (used, _))| *used).map(|(k, _)| *k) {
+            self.entries.remove(&oldest);
+        }
+        self.clock += 1;
+        self.entries.insert(hash, (self.clock, lines));
+    }
+}
+
+thread_local! {
+    // 流式输出中的回复每帧重新渲染，缓存高亮结果避免重复解析已完成的代码块
+    static CACHE: RefCell<Cache> = RefCell::new(Cache::default());
+}
+
+fn theme() -> &'static Theme {
+    let name = get_config().read().unwrap().theme.clone();
+    let themes = &assets().themes.themes;
+    themes.get(&name).unwrap_or_else(|| {
+        // 每帧都会查询主题，只提示一次
+        static WARNED: AtomicBool = AtomicBool::new(false);
+        if !WARNED.swap(true, Ordering::Relaxed) {
+            warn!("未知的高亮主题 <{}>，使用默认主题", name);
+        }
+        &themes["base16-ocean.dark"]
+    })
+}
+
+/// 当前主题的背景色
+pub fn background() -> Option<Color> {
+    theme().settings.background.map(|c| Color::Rgb(c.r, c.g, c.b))
+}
+
+/// 按代码块语言标记高亮，未知语言返回 None
+pub fn highlight_code(lang: &str, code: &str) -> Option<Vec<StyledLine>> {
+    let token = lang.split([' ', ',', '{']).next().unwrap_or("");
+    let syntax = assets().syntaxes.find_syntax_by_token(token)?;
+    Some(cached(token, code, || highlight_lines(syntax, code.lines())))
+}
+
+/*
+ * -------- [ read 结果高亮 ] --------
+ * read_file 的输出形如 `12) fn main() {`，按文件扩展名高亮代码部分，
+ * 绝对行号保留在左侧行号栏中
+ */
+pub fn highlight_numbered(path: &str, numbered: &str) -> Vec<StyledLine> {
+    cached(path, numbered, || {
+        let (numbers, code): (Vec<&str>, Vec<&str>) = numbered.lines()
+            .map(|line| line.split_once(") ").unwrap_or(("", line)))
+            .unzip();
+
+        let syntaxes = &assets().syntaxes;
+        let syntax = Path::new(path).extension()
+            .and_then(|ext| syntaxes.find_syntax_by_extension(&ext.to_string_lossy()))
+            .unwrap_or_else(|| syntaxes.find_syntax_plain_text());
+
+        let width = numbers.iter().map(|n| n.len()).max().unwrap_or(0);
+        let gutter = Style::default().fg(Color::DarkGray);
+        highlight_lines(syntax, code.into_iter())
+            .into_iter()
+            .zip(numbers)
+            .map(|(spans, number)| {
+                let mut line = vec![Span::styled(format!("{:>width$} │ ", number), gutter)];
+                line.extend(spans);
+                line
+            })
+            .collect()
+    })
+}
+
+fn highlight_lines<'a>(syntax: &SyntaxReference, lines: impl Iterator<Item = &'a str>) -> Vec<StyledLine> {
+    let syntaxes = &assets().syntaxes;
+    let mut highlighter = HighlightLines::new(syntax, theme());
+
+    lines.map(|line| {
+        // 语法定义按带换行符的行解析
+        let line = format!("{}\n", line);
+        match highlighter.highlight_line(&line, syntaxes) {
+            Ok(regions) => regions.into_iter()
+                .map(|(style, text)| Span::styled(text.trim_end_matches('\n').to_string(), convert_style(style)))
+                .filter(|span| !span.content.is_empty())
+                .collect(),
+            Err(_) => vec![Span::raw(line.trim_end_matches('\n').to_string())],
+        }
+    }).collect()
+}
+
+fn convert_style(style: syntect::highlighting::Style) -> Style {
+    let fg = style.foreground;
+    let mut result = Style::default().fg(Color::Rgb(fg.r, fg.g, fg.b));
+    if style.font_style.contains(FontStyle::BOLD) {
+        result = result.add_modifier(Modifier::BOLD);
+    }
+    if style.font_style.contains(FontStyle::ITALIC) {
+        result = result.add_modifier(Modifier::ITALIC);
+    }
+    if style.font_style.contains(FontStyle::UNDERLINE) {
+        result = result.add_modifier(Modifier::UNDERLINED);
+    }
+    result
+}
+
+fn cached(key: &str, text: &str, compute: impl FnOnce() -> Vec<StyledLine>) -> Vec<StyledLine> {
+    let mut hasher = DefaultHasher::new();
+    (key, text, &get_config().read().unwrap().theme).hash(&mut hasher);
+    let hash = hasher.finish();
+
+    if let Some(lines) = CACHE.with_borrow_mut(|cache| cache.get(hash)) {
+        return lines
+    }
+
+    let lines = compute();
+    CACHE.with_borrow_mut(|cache| cache.insert(hash, lines.clone()));
+    lines
+}
@@ -0,0 +1,301 @@
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::fs;
+use std::path::PathBuf;
+use std::rc::Rc;
+use tracing::warn;
+use unicode_width::UnicodeWidthChar;
+use crate::config_manager::{get_home_path, write_private};
+
+const HISTORY_FILENAME: &str = "input_history.json";
+const HISTORY_LIMIT: usize = 500;
+
+/*
+ * -------- [ 多行输入编辑器 ] --------
+ * - cursor 为 text 中的字节下标，始终落在字符边界上
+ * - history 为历史提问（跨会话持久化，各标签页共享），history_index 为当前回溯位置的绝对序号
+ * - draft 保存回溯历史前尚未发送的输入，回溯结束时恢复
+ */
+pub struct InputEditor {
+    text: String,
+    cursor: usize,
+    history: Rc<RefCell<SharedHistory>>,
+    history_index: Option<usize>,
+    draft: String,
+}
+
+/// 各标签页共享的历史；base 为已淘汰的条目数，entries[i] 的绝对序号为 base + i，
+/// 淘汰最早的条目不会让其他标签页的回溯位置错位
+struct SharedHistory {
+    entries: VecDeque<String>,
+    base: usize,
+}
+
+impl SharedHistory {
+    fn end(&self) -> usize {
+        self.base + self.entries.len()
+    }
+
+    fn get(&self, index: usize) -> String {
+        self.entries[index - self.base].clone()
+    }
+}
+
+impl InputEditor {
+    pub fn new() -> Self {
+        Self {
+            text: String::new(),
+            cursor: 0,
+            history: Rc::new(RefCell::new(SharedHistory { entries: load_history(), base: 0 })),
+            history_index: None,
+            draft: String::new(),
+        }
+    }
+
+    /// 创建与当前编辑器共享历史的空编辑器
+    pub fn share_history(&self) -> Self {
+        Self {
+            text: String::new(),
+            cursor: 0,
+            history: Rc::clone(&self.history),
+            history_index: None,
+            draft: String::new(),
+        }
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.text.is_empty()
+    }
+
+    /// 行数（至少为 1）
+    pub fn line_count(&self) -> usize {
+        self.text.split('\n').count()
+    }
+
+    /// 光标所在的 (行, 显示列)
+    pub fn cursor_position(&self) -> (usize, usize) {
+        let before = &self.text[..self.cursor];
+        let row = before.matches('\n').count();
+        let line_start = before.rfind('\n').map(|i| i + 1).unwrap_or(0);
+        (row, display_width(&self.text[line_start..self.cursor]))
+    }
+
+    /// 取出输入内容并记入历史
+    pub fn submit(&mut self) -> String {
+        let text = std::mem::take(&mut self.text);
+        self.cursor = 0;
+        self.history_index = None;
+        self.draft.clear();
+
+        let mut history = self.history.borrow_mut();
+        if !text.trim().is_empty() && history.entries.back() != Some(&text) {
+            history.entries.push_back(text.clone());
+            if history.entries.len() > HISTORY_LIMIT {
+                history.entries.pop_front();
+                history.base += 1;
+            }
+            save_history(&history.entries);
+        }
+        drop(history);
+
+        text
+    }
+
+    /// 替换全部内容，光标置于末尾
+    pub fn set_text(&mut self, text: String) {
+        self.cursor = text.len();
+        self.text = text;
+    }
+
+    // --- [ 编辑 ] ---
+    pub fn insert_char(&mut self, c: char) {
+        self.text.insert(self.cursor, c);
+        self.cursor += c.len_utf8();
+    }
+
+    /// 插入粘贴内容，统一换行符
+    pub fn insert_str(&mut self, s: &str) {
+        let s = s.replace("\r\n", "\n").replace('\r', "\n");
+        self.text.insert_str(self.cursor, &s);
+        self.cursor += s.len();
+    }
+
+    pub fn newline(&mut self) {
+        self.insert_char('\n');
+    }
+
+    pub fn backspace(&mut self) {
+        if let Some(prev) = self.prev_boundary() {
+            self.text.replace_range(prev..self.cursor, "");
+            self.cursor = prev;
+        }
+    }
+
+    pub fn delete(&mut self) {
+        if let Some(next) = self.next_boundary() {
+            self.text.replace_range(self.cursor..next, "");
+        }
+    }
+
+    /// 删除光标前的一个单词
+    pub fn delete_word_left(&mut self) {
+        let start = self.word_left_boundary();
+        self.text.replace_range(start..self.cursor, "");
+        self.cursor = start;
+    }
+
+    // --- [ 光标移动 ] ---
+    pub fn move_left(&mut self) {
+        if let Some(prev) = self.prev_boundary() {
+            self.cursor = prev;
+        }
+    }
+
+    pub fn move_right(&mut self) {
+        if let Some(next) = self.next_boundary() {
+            self.cursor = next;
+        }
+    }
+
+    pub fn move_word_left(&mut self) {
+        self.cursor = self.word_left_boundary();
+    }
+
+    pub fn move_word_right(&mut self) {
+        let rest = &self.text[self.cursor..];
+        let skip_space = rest.len() - rest.trim_start().len();
+        let word = rest[skip_space..].find(char::is_whitespace).unwrap_or(rest.len() - skip_space);
+        self.cursor += skip_space + word;
+    }
+
+    /// 行首
+    pub fn move_home(&mut self) {
+        self.cursor = self.text[..self.cursor].rfind('\n').map(|i| i + 1).unwrap_or(0);
+    }
+
+    /// 行尾
+    pub fn move_end(&mut self) {
+        self.cursor += self.text[self.cursor..].find('\n').unwrap_or(self.text.len() - self.cursor);
+    }
+
+    /// 上移一行；已在首行时回溯更早的历史
+    pub fn move_up(&mut self) {
+        let (row, col) = self.cursor_position();
+        if row == 0 {
+            self.history_prev();
+        } else {
+            self.move_to(row - 1, col);
+        }
+    }
+
+    /// 下移一行；已在末行时前进到较新的历史
+    pub fn move_down(&mut self) {
+        let (row, col) = self.cursor_position();
+        if row + 1 >= self.line_count() {
+            self.history_next();
+        } else {
+            self.move_to(row + 1, col);
+        }
+    }
+
+    // --- [ 历史回溯 ] ---
+    fn history_prev(&mut self) {
+        let history = self.history.borrow();
+        let index = match self.history_index {
+            None if history.entries.is_empty() => return,
+            None => {
+                self.draft = self.text.clone();
+                history.end() - 1
+            }
+            // 其他标签页可能已追加或淘汰历史，绝对序号仍然有效
+            Some(i) if i > history.base => i - 1,
+            Some(_) => return,
+        };
+        let text = history.get(index);
+        drop(history);
+        self.history_index = Some(index);
+        self.set_text(text);
+    }
+
+    fn history_next(&mut self) {
+        let history = self.history.borrow();
+        match self.history_index {
+            None => {}
+            Some(i) if i + 1 < history.end() => {
+                let index = (i + 1).max(history.base);
+                let text = history.get(index);
+                drop(history);
+                self.history_index = Some(index);
+                self.set_text(text);
+            }
+            Some(_) => {
+                drop(history);
+                self.history_index = None;
+                let draft = std::mem::take(&mut self.draft);
+                self.set_text(draft);
+            }
+        }
+    }
+
+    // --- [ 辅助函数 ] ---
+    fn prev_boundary(&self) -> Option<usize> {
+        self.text[..self.cursor].char_indices().next_back().map(|(i, _)| i)
+    }
+
+    fn next_boundary(&self) -> Option<usize> {
+        self.text[self.cursor..].chars().next().map(|c| self.cursor + c.len_utf8())
+    }
+
+    fn word_left_boundary(&self) -> usize {
+        let before = self.text[..self.cursor].trim_end();
+        before.rfind(char::is_whitespace)
+            .map(|i| i + before[i..].chars().next().unwrap().len_utf8())
+            .unwrap_or(0)
+    }
+
+    /// 移动到指定行，尽量保持显示列不变
+    fn move_to(&mut self, row: usize, col: usize) {
+        let line_start: usize = self.text.split('\n').take(row).map(|l| l.len() + 1).sum();
+        let line = self.text[line_start..].split('\n').next().unwrap_or("");
+
+        let mut width = 0;
+        let mut offset = line.len();
+        for (i, c) in line.char_indices() {
+            if width >= col {
+                offset = i;
+                break;
+            }
+            width += c.width().unwrap_or(0);
+        }
+        self.cursor = line_start + offset;
+    }
+}
+
+fn display_width(s: &str) -> usize {
+    s.chars().map(|c| c.width().unwrap_or(0)).sum()
+}
+
+fn history_path() -> Option<PathBuf> {
+    get_home_path().ok().map(|p| p.join(HISTORY_FILENAME))
+}
+
+fn load_history() -> VecDeque<String> {
+    let Some(path) = history_path() else { return VecDeque::new() };
+    fs::read_to_string(&path)
+        .ok()
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .unwrap_or_default()
+}
+
+/// 输入历史可能含有粘贴的密钥，与配置文件一样仅所有者可读写
+fn save_history(history: &VecDeque<String>) {
+    let Some(path) = history_path() else { return };
+    let json = serde_json::to_string(history).expect("输入历史 -> JSON 转换错误");
+    if let Err(e) = write_private(&path, &json) {
+        warn!("无法写入输入历史: {}", e);
+    }
+}
@@ -1,11 +1,21 @@
 use std::sync::mpsc;
 use std::{env, thread};
 use std::fs;
+use serde::{Deserialize, Serialize};
 use tracing::info;
 use crate::api_client::ApiClient;
+use crate::compact::compact;
+use crate::doctor;
+use crate::config_manager::{get_workspace_path, Config};
 use crate::app::*;
 use crate::ui::Ui;
-use crate::worker_thread::{parse_tool_call, WorkerThread};
+use crate::pins::{self, Pins};
+use crate::provider::with_reasoning;
+use crate::redact::redact;
+use crate::transcript::EntryKind;
+use crate::tokenizer::{calibrate, count_messages};
+use crate::usage::UsageLog;
+use crate::worker_thread::{normalize_path, parse_tool_call, WorkerThread};
 
 pub struct IOThread {
     tx_to_io: mpsc::Sender<AppMessage>,
@@ -23,7 +33,7 @@ impl IOThread {
         let (tx_to_ui, rx_from_io) = mpsc::channel();
 
         thread::spawn(move || {
-            let client = match ApiClient::new() {
+            let mut client = match ApiClient::new() {
                 Err(e) => { eprintln!("{}", e); std::process::exit(1) }
                 Ok(c) => c
             };
@@ -31,33 +41,68 @@ impl IOThread {
             let mut history = History::new(&client, tx_to_ui.clone());
 
             while let Ok(msg) = rx_from_ui.recv() {
-                let mut handle_system_result = |result: String| {
+                // 补丁应用成功后，先将各角色上下文中该文件之前的读取结果标记为过期
+                if let AppMessage::SysMsg(SystemMessage::DiffResult(_, file_path, output)) = &msg
+                    && output.success
+                {
+                    let stale = history.invalidate_reads(file_path);
+                    if stale > 0 {
+                        let notice = format!("<{}> 已修改，{} 条之前的 read 结果已标记为过期", file_path, stale);
+                        let _ = tx_to_ui.send(AppMessage::SysMsg(SystemMessage::Notice(notice)));
+                    }
+                }
+
+                let mut handle_system_result = |model: Model, result: String| {
                     let chat_msg = ChatMessage { role: "system".into(), content: result };
-                    history.push(chat_msg);
-                    history.send(&client, tx_to_ui.clone())
+                    history.push(model, chat_msg);
+                    history.send(model, &client, tx_to_ui.clone())
                 };
 
                 match msg {
-                    AppMessage::UserQuery(content) => {
+                    AppMessage::UserQuery(model, content) => {
                         let chat_msg = ChatMessage { role: "user".into(), content };
-                        history.push(chat_msg);
-                        history.send(&client, tx_to_ui.clone());
+                        history.push(model, chat_msg);
+                        history.send(model, &client, tx_to_ui.clone());
                     }
-                    AppMessage::AIMsg(AssistantMessage::AssistantReply(content)) => {
+                    AppMessage::AIMsg(AssistantMessage::AssistantReply(model, content)) => {
                         let chat_msg = ChatMessage { role: "assistant".into(), content };
-                        history.push(chat_msg);
+                        history.push(model, chat_msg);
+                    }
+                    AppMessage::SysMsg(SystemMessage::ExecResult(model, output)) => {
+                        handle_system_result(model, output.content);
                     }
-                    AppMessage::SysMsg(SystemMessage::ExecResult(result)) => {
-                        handle_system_result(result);
+                    AppMessage::SysMsg(SystemMessage::ReadResult(model, filename, output)) => {
+                        let content = if output.success { read_context(&filename, &output.content) } else { output.content };
+                        handle_system_result(model, content);
                     }
-                    AppMessage::SysMsg(SystemMessage::ReadResult(result)) => {
-                        handle_system_result(result);
+                    AppMessage::SysMsg(SystemMessage::DiffResult(model, _, output)) => {
+                        handle_system_result(model, output.content);
                     }
-                    AppMessage::SysMsg(SystemMessage::DiffResult(result)) => {
-                        handle_system_result(result);
+                    AppMessage::SysMsg(SystemMessage::Rejected(model, feedback)) => {
+                        handle_system_result(model, feedback);
+                    }
+                    AppMessage::CtrlMsg(ctrl) => {
+                        let result = match ctrl {
+                            ControlMessage::ReloadConfig => Config::init().and_then(|_| ApiClient::new()).map(|c| {
+                                client = c;
+                                "配置已重新载入".to_string()
+                            }),
+                            ControlMessage::Doctor => match doctor::run(&client) {
+                                (report, true) => Ok(format!("连接诊断:\n{}", report.trim_end())),
+                                (report, false) => Err(format!("连接诊断:\n{}", report.trim_end())),
+                            },
+                            ctrl => history.control(ctrl)
+                        };
+                        let reply = match result {
+                            Ok(msg) => SystemMessage::Notice(msg),
+                            Err(e) => SystemMessage::SystemLog(e)
+                        };
+                        let _ = tx_to_ui.send(AppMessage::SysMsg(reply));
                     }
                     _ => {}
                 }
+
+                history.report(&tx_to_ui);
             }
         });
 
@@ -71,63 +116,153 @@ impl IOThread {
     pub fn handle_response(&mut self, ui: &mut Ui, worker_thread: &mut WorkerThread) {
         if let Ok(msg) = self.rx_from_io.try_recv() {
             match msg {
-                AppMessage::AIMsg(AssistantMessage::ModelChunk(chunk)) => {
-                    ui.current_ai_response.push_str(&chunk);
-                    ui.auto_scroll();
+                AppMessage::AIMsg(AssistantMessage::StreamStart(model)) => ui.tab_of(model).start_stream(),
+
+                AppMessage::AIMsg(AssistantMessage::ModelChunk(model, chunk)) => {
+                    let tab = ui.tab_of(model);
+                    tab.stream_chunk(&chunk);
+                    tab.current_ai_response.push_str(&chunk);
+                    tab.scroll.mark_new_output();
                 }
 
-                AppMessage::AIMsg(AssistantMessage::TaskComplete) => {
-                    let full_msg = ui.current_ai_response.clone();
+                AppMessage::AIMsg(AssistantMessage::ReasoningChunk(model, chunk)) => {
+                    let tab = ui.tab_of(model);
+                    tab.stream_chunk(&chunk);
+                    tab.current_reasoning.push_str(&chunk);
+                    tab.scroll.mark_new_output();
+                }
 
-                    let model = match get_model().read().unwrap().clone() {
-                        Model::MELCHIOR => "MELCHIOR",
-                        Model::CASPER_I => "CASPER-I",
-                        Model::CASPER_II => "CASPER-II",
-                        Model::BALTHAZAR => "BALTHAZAR"
-                    };
+                AppMessage::AIMsg(AssistantMessage::TaskComplete(model)) => {
+                    let tab = ui.tab_of(model);
+                    tab.stream = None;
+                    // 取出当前正在生成的回复，避免重复显示
+                    let full_msg = std::mem::take(&mut tab.current_ai_response);
+                    let reasoning = std::mem::take(&mut tab.current_reasoning);
 
                     // 刷新屏幕显示
-                    ui.history_display.push_str(&format!("\nASSISTANT: {}\n{}\n", model, full_msg));
-                    // 清空当前正在生成的回复，避免重复显示
-                    ui.current_ai_response.clear();
-                    // 更新 AGENT 输出上下文
-                    self.send(AppMessage::AIMsg(AssistantMessage::AssistantReply(full_msg.clone())));
+                    if !reasoning.trim().is_empty() {
+                        tab.push(EntryKind::Reasoning(reasoning.clone()));
+                    }
+                    tab.push(EntryKind::Assistant { model, content: full_msg.clone() });
+                    // 更新 AGENT 输出上下文；思考过程默认不写入，避免占用上下文
+                    let context = with_reasoning(&full_msg, &reasoning);
+                    self.send(AppMessage::AIMsg(AssistantMessage::AssistantReply(model, context)));
 
                     /*
                      * --------[ 这里触发解析工具调用 ] --------
+                     * 只解析回复正文，思考过程中的示例调用不会被执行
                      */
                     if let Some(call) = parse_tool_call(full_msg) {
                         info!("正在处理工具调用");
                         match call.tool {
                             Tool::Exec =>
-                                ui.pending_action = PendingAction::ConfirmExec(call.content),
-                            Tool::Read =>
-                                worker_thread.send(AppMessage::SysMsg(SystemMessage::Read(call.content))),
+                                tab.pending_action = PendingAction::ConfirmExec(call.content),
+                            Tool::Read => {
+                                tab.push(EntryKind::ToolCall { tool: "read", detail: call.content.clone(), result: None });
+                                worker_thread.send(AppMessage::SysMsg(SystemMessage::Read(model, call.content)))
+                            }
                             Tool::Diff(file_path) =>
-                                ui.pending_action = PendingAction::ConfirmDiff(file_path, call.content),
+                                tab.pending_action = PendingAction::ConfirmDiff(file_path, call.content),
                             _ => {}
                         }
                     }
                 }
 
-                AppMessage::SysMsg(SystemMessage::SystemLog(log)) =>
-                    ui.history_display.push_str(&format!("\n[ERROR]: {}\n", log)),
+                AppMessage::SysMsg(SystemMessage::SystemLog(log)) => ui.push_error(&log),
+                AppMessage::SysMsg(SystemMessage::Notice(msg)) => ui.push_notice(&msg),
+                AppMessage::SysMsg(SystemMessage::ContextTokens(tokens)) => ui.context_tokens = tokens,
+                AppMessage::SysMsg(SystemMessage::UsageTotals(totals)) => ui.usage = totals,
+                AppMessage::SysMsg(SystemMessage::Compacted(model, msg)) => ui.tab_of(model).push(EntryKind::Notice(msg)),
                 _ => {}
             }
         }
     }
 }
 
+const SESSION_DIR: &str = "sessions";
+
+// 写入上下文的读取结果以 `System: Read Result <路径>:` 一行开头
+const READ_PREFIX: &str = "System: Read Result <";
+const READ_STALE_NOTE: &str = "[已过期] 该文件之后已被 patch 修改，此处的内容与行号不再有效；如需继续修改，请重新 read。";
+
+/// 写入上下文的读取结果，开头注明文件路径
+pub fn read_context(filename: &str, content: &str) -> String {
+    format!("{}{}>:\n{}", READ_PREFIX, filename, content)
+}
+
+/// 读取结果对应的文件路径；已过期的结果返回 None
+fn read_source(content: &str) -> Option<&str> {
+    content.lines().next()?.strip_prefix(READ_PREFIX)?.strip_suffix(">:")
+}
+
+/// 将一组消息中该文件之前的读取结果替换为过期说明，返回替换的条数
+pub fn invalidate_reads(messages: &mut [ChatMessage], file_path: &str) -> usize {
+    let Ok(target) = normalize_path(file_path) else { return 0 };
+    let mut stale = 0;
+    for msg in messages.iter_mut().filter(|m| m.role == "system") {
+        let Some(path) = read_source(&msg.content) else { continue };
+        if normalize_path(path).is_ok_and(|p| p == target) {
+            msg.content = format!("{}{}>: {}", READ_PREFIX, path, READ_STALE_NOTE);
+            stale += 1;
+        }
+    }
+    stale
+}
+
+/// 各模型的初始上下文（系统提示词）
+pub fn initial_history(model: &Model) -> Vec<ChatMessage> {
+    let to_msg = |content: String| {
+        ChatMessage { role: "system".into(), content }
+    };
+
+    match model {
+        Model::MELCHIOR => {
+            let cwd = env::current_dir().unwrap().to_string_lossy().to_string();
+            let mut paths = cwd.clone();
+            for entry in fs::read_dir(".").unwrap() {
+                paths.push_str("\n|-- ");
+                paths.push_str(entry.unwrap().path().to_str().unwrap());
+            }
+
+            info!("MELCHIOR 提示词目录结构：\n```\n{}\n```", paths);
+            vec![to_msg(MELCHIOR_PROMPT.replace("{{ENTRIES}}", paths.as_str()).to_string())]
+        }
+        Model::CASPER_I => vec![to_msg(CASPER_I_PROMPT.to_string())],
+        Model::CASPER_II => vec![to_msg(CASPER_II_PROMPT.to_string())],
+        Model::BALTHAZAR => Vec::new()
+    }
+}
+
+#[derive(Serialize, Deserialize)]
 struct History {
     melchior_history: Vec<ChatMessage>,
     casper_i_history: Vec<ChatMessage>,
     casper_ii_history: Vec<ChatMessage>,
-    balthazar_history: Vec<ChatMessage>
+    balthazar_history: Vec<ChatMessage>,
+    // 旧版会话文件没有该字段
+    #[serde(default)]
+    usage: UsageLog,
+    #[serde(default)]
+    pins: Pins
 }
 
 impl History {
-    fn match_history(&mut self) -> &mut Vec<ChatMessage> {
-        match get_model().read().unwrap().clone() {
+    pub fn new(client: &ApiClient, sender: mpsc::Sender<AppMessage>) -> Self {
+        let mut history = Self {
+            melchior_history: initial_history(&Model::MELCHIOR),
+            casper_i_history: initial_history(&Model::CASPER_I),
+            casper_ii_history: initial_history(&Model::CASPER_II),
+            balthazar_history: initial_history(&Model::BALTHAZAR),
+            usage: UsageLog::default(),
+            pins: Pins::default()
+        };
+
+        history.send(Model::MELCHIOR, client, sender);
+        history
+    }
+
+    fn history_of(&mut self, model: &Model) -> &mut Vec<ChatMessage> {
+        match model {
             Model::MELCHIOR => &mut self.melchior_history,
             Model::CASPER_I => &mut self.casper_i_history,
             Model::CASPER_II => &mut self.casper_ii_history,
@@ -135,50 +270,122 @@ impl History {
         }
     }
 
-    pub fn new(client: &ApiClient, sender: mpsc::Sender<AppMessage>) -> Self {
-        let to_msg = |content: String| {
-            ChatMessage { role: "system".into(), content }
-        };
-
-        let cwd = env::current_dir().unwrap().to_string_lossy().to_string();
-        let mut paths = cwd.clone();
-        for entry in fs::read_dir(".").unwrap() {
-            paths.push_str("\n|-- ");
-            paths.push_str(entry.unwrap().path().to_str().unwrap());
+    /*
+     * -------- [ 斜杠命令处理 ] --------
+     * 返回显示给用户的结果信息
+     */
+    fn control(&mut self, ctrl: ControlMessage) -> Result<String, String> {
+        match ctrl {
+            ControlMessage::ClearHistory(target) => {
+                *self.history_of(&target) = initial_history(&target);
+                Ok(format!("已清空 {} 的上下文", target.name()))
+            }
+            ControlMessage::ShowHistory(model) => {
+                let history = self.history_of(&model);
+                let mut output = format!("{} 上下文共 {} 条消息:", model.name(), history.len());
+                for (i, msg) in history.iter().enumerate() {
+                    let preview: String = msg.content.lines().next().unwrap_or("").chars().take(60).collect();
+                    output.push_str(&format!("\n  #{} {:<9} ({} 字符) {}", i, msg.role, msg.content.chars().count(), preview));
+                }
+                Ok(output)
+            }
+            ControlMessage::ShowUsage => Ok(self.usage.report()),
+            ControlMessage::Pin(model, pin) => self.pins.add(model, pin),
+            ControlMessage::ShowPins(model) => Ok(self.pins.list(model)),
+            ControlMessage::Unpin(model, index) => self.pins.remove(model, index),
+            ControlMessage::Undo(model) => {
+                let history = self.history_of(&model);
+                let last_user = history.iter().rposition(|m| m.role == "user")
+                    .ok_or(format!("{} 没有可撤销的对话", model.name()))?;
+                let removed = history.len() - last_user;
+                history.truncate(last_user);
+                Ok(format!("已撤销 {} 的上一轮对话（移除 {} 条消息）", model.name(), removed))
+            }
+            ControlMessage::SaveSession(name) => {
+                let path = get_workspace_path()?.join(SESSION_DIR);
+                fs::create_dir_all(&path)
+                    .map_err(|e| format!("无法创建会话目录 <{}>: {}", path.to_string_lossy(), e))?;
+                let path = path.join(format!("{}.json", name));
+                let json = serde_json::to_string_pretty(self)
+                    .expect("History 结构体 -> JSON 转换错误");
+                fs::write(&path, json)
+                    .map_err(|e| format!("无法写入文件 <{}>: {}", path.to_string_lossy(), e))?;
+                Ok(format!("会话已保存至 <{}>", path.to_string_lossy()))
+            }
+            ControlMessage::LoadSession(name) => {
+                let path = get_workspace_path()?.join(SESSION_DIR).join(format!("{}.json", name));
+                let content = fs::read_to_string(&path)
+                    .map_err(|e| format!("无法读取会话文件 <{}>: {}", path.to_string_lossy(), e))?;
+                *self = serde_json::from_str(&content)
+                    .map_err(|e| format!("会话文件 JSON 解析错误 <{}>: {}", path.to_string_lossy(), e))?;
+                Ok(format!("已从 <{}> 载入会话", path.to_string_lossy()))
+            }
+            // 需要访问 ApiClient，由 IO 线程循环直接处理；误路由到这里时不做任何操作
+            ControlMessage::ReloadConfig | ControlMessage::Doctor => Err("该操作应由 IO 线程直接处理，已忽略".into()),
         }
+    }
 
-        info!("MELCHIOR 提示词目录结构：\n```\n{}\n```", paths);
-
-        let mut melchior_history = Vec::<ChatMessage>::new();
-        melchior_history.push(to_msg(MELCHIOR_PROMPT.replace("{{ENTRIES}}", paths.as_str()).to_string()));
-        let mut casper_i_history = Vec::<ChatMessage>::new();
-        casper_i_history.push(to_msg(CASPER_I_PROMPT.to_string()));
-        let mut casper_ii_history = Vec::<ChatMessage>::new();
-        casper_ii_history.push(to_msg(CASPER_II_PROMPT.to_string()));
-
-        let history = Self {
-            melchior_history,
-            casper_i_history,
-            casper_ii_history,
-            balthazar_history: Vec::<ChatMessage>::new()
-        };
+    /*
+     * -------- [ 过期读取结果 ] --------
+     * 文件被补丁修改后，上下文中之前的 read 结果（含行号）已与磁盘不一致，
+     * 小模型容易据此生成错误的 diff。将其替换为简短的过期说明，提示重新读取；
+     * 通过结果开头的文件路径识别，因此压缩、撤销与载入会话后依然有效
+     */
+    fn invalidate_reads(&mut self, file_path: &str) -> usize {
+        let stale = Model::ALL.iter().map(|model| invalidate_reads(self.history_of(model), file_path)).sum();
+        info!("<{}> 已修改，标记 {} 条过期的读取结果", file_path, stale);
+        stale
+    }
 
-        history.send(client, sender);
-        history
+    /// 写入上下文前脱敏，避免密钥被发送给模型
+    pub fn push(&mut self, model: Model, mut msg: ChatMessage) {
+        msg.content = redact(&msg.content);
+        self.history_of(&model).push(msg)
     }
 
-    pub fn push(&mut self, msg: ChatMessage) {
-        Self::match_history(self).push(msg)
+    /// 向 UI 报告各角色上下文的估算 token 数与会话用量
+    fn report(&self, sender: &mpsc::Sender<AppMessage>) {
+        let histories = [
+            &self.melchior_history,
+            &self.casper_i_history,
+            &self.casper_ii_history,
+            &self.balthazar_history
+        ];
+        let tokens = std::array::from_fn(|i| {
+            let model = Model::ALL[i];
+            count_messages(&model, histories[i]) + self.pins.message(model).map_or(0, |m| count_messages(&model, &[m]))
+        });
+        let _ = sender.send(AppMessage::SysMsg(SystemMessage::ContextTokens(tokens)));
+        let _ = sender.send(AppMessage::SysMsg(SystemMessage::UsageTotals(self.usage.totals())));
     }
 
-    pub fn send(&self, api_client: &ApiClient, sender: mpsc::Sender<AppMessage>) {
-        let history = match get_model().read().unwrap().clone() {
+    pub fn send(&mut self, model: Model, api_client: &ApiClient, sender: mpsc::Sender<AppMessage>) {
+        // 固定上下文在每次请求前重新组装，不写入历史
+        let pinned = self.pins.message(model);
+        let reserved = pinned.as_ref().map_or(0, |m| count_messages(&model, std::slice::from_ref(m)));
+
+        // 超出上下文预算时先压缩
+        let notify = |msg: String| {
+            let _ = sender.send(AppMessage::SysMsg(SystemMessage::Compacted(model, msg)));
+        };
+        compact(model, self.history_of(&model), reserved, api_client, &notify);
+        self.report(&sender);
+        let mut history = match model {
             Model::MELCHIOR => self.melchior_history.clone(),
             Model::CASPER_I => self.casper_i_history.clone(),
             Model::CASPER_II => self.casper_ii_history.clone(),
             Model::BALTHAZAR => self.balthazar_history.clone()
         };
+        if let Some(pinned) = pinned {
+            pins::insert(model, &mut history, pinned);
+        }
 
-        api_client.send_chat_stream(history, sender);
+        let counted = count_messages(&model, &history);
+        let usage = api_client.send_chat_stream(model, history, sender.clone());
+        if let Some(reported) = usage.prompt_tokens {
+            calibrate(&model, counted, reported);
+        }
+        self.usage.record(model, usage);
+        self.report(&sender);
     }
 }
\ No newline at end of file
@@ -5,16 +5,40 @@ mod app;
 mod io_thread;
 mod event_handler;
 mod worker_thread;
+mod input_editor;
+mod command;
+mod markdown;
+mod highlight;
+mod transcript;
+mod tokenizer;
+mod config_layers;
+mod cli;
+mod setup;
+mod redact;
+mod doctor;
+mod provider;
+mod usage;
+mod compact;
+mod pins;
+mod headless;
 
 use crossterm::{
-    terminal::{enable_raw_mode, EnterAlternateScreen},
+    event::{
+        DisableBracketedPaste, DisableMouseCapture, EnableBracketedPaste, EnableMouseCapture, KeyboardEnhancementFlags,
+        PopKeyboardEnhancementFlags, PushKeyboardEnhancementFlags,
+    },
+    terminal::{enable_raw_mode, supports_keyboard_enhancement, EnterAlternateScreen},
     ExecutableCommand,
 };
 
+use clap::Parser;
 use tracing_subscriber::{fmt, prelude::*, EnvFilter};
 use std::io;
 use crossterm::terminal::{disable_raw_mode, LeaveAlternateScreen};
 use tracing::info;
+use crate::cli::{Cli, CliCommand, ConfigAction};
+use crate::config_layers::set_cli_overrides;
+use crate::redact::RedactWriter;
 use crate::config_manager::*;
 use crate::app::*;
 use crate::event_handler::handle_event;
@@ -24,6 +48,8 @@ use crate::worker_thread::WorkerThread;
 
 #[cfg(unix)]
 fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let cli = Cli::parse();
+
     // --- [ 初始化日志 ] ---
     let log_file = std::fs::File::create(".oxicodent.log")?;
     tracing_subscriber::registry()
@@ -31,11 +57,60 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             EnvFilter::try_from_default_env()
                 .unwrap_or_else(|_| EnvFilter::new("debug"))  // ← 默认 debug 级别
         )
-        .with(fmt::layer().with_writer(log_file))
+        .with(fmt::layer().with_writer(move || RedactWriter(log_file.try_clone().expect("无法写入日志文件"))))
         .init();
 
     info!(":: Oxicodent ::    (v{})", env!("CARGO_PKG_VERSION"));
 
+    // --- 首次运行：配置向导 ---
+    if cli.command.is_none() && !Config::exists()? {
+        if !setup::run()? {
+            eprintln!("已取消初始配置");
+            return Ok(())
+        }
+        info!("配置向导已完成");
+    }
+
+    // --- 加载配置 ---
+    let loaded = cli.overrides.into_overrides()
+        .map(set_cli_overrides)
+        .and_then(|_| Config::init());
+    if let Err(e) = loaded {
+        eprintln!("{}", e);
+        std::process::exit(1)
+    }
+
+    // --- 非交互子命令 ---
+    if let Some(CliCommand::Config { action: ConfigAction::Show }) = cli.command {
+        let config = get_config().read().unwrap();
+        for warning in &config.warnings {
+            eprintln!("警告: {}", warning);
+        }
+        print!("{}", config.describe());
+        return Ok(())
+    }
+    if let Some(CliCommand::Config { action: ConfigAction::Migrate }) = cli.command {
+        match Config::migrate() {
+            Ok(message) => println!("{}", message),
+            Err(e) => {
+                eprintln!("{}", e);
+                std::process::exit(1)
+            }
+        }
+        return Ok(())
+    }
+    if let Some(CliCommand::Doctor) = cli.command {
+        let (report, ok) = doctor::run(&api_client::ApiClient::new()?);
+        print!("{}", report);
+        std::process::exit(if ok { 0 } else { 1 })
+    }
+    if let Some(CliCommand::Ask { role, yes, question }) = cli.command {
+        std::process::exit(headless::ask(role, question, yes))
+    }
+    if let Some(CliCommand::Run { spec, role, yes }) = cli.command {
+        std::process::exit(headless::run_spec(role, &spec, yes))
+    }
+
     // --- 创建 IO 线程 ---
     let mut io_thread = IOThread::spawn()?;
     info!("IO 线程已创建");
@@ -46,11 +121,21 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     // --- 创建 UI ---
     let mut ui = Ui::new();
+    for warning in &get_config().read().unwrap().warnings {
+        ui.push_error(warning);
+    }
     info!("UI 已创建");
 
     // --- 终端初始化 ---
     enable_raw_mode()?;
     io::stdout().execute(EnterAlternateScreen)?;
+    io::stdout().execute(EnableBracketedPaste)?;
+    io::stdout().execute(EnableMouseCapture)?;
+    // 支持时启用按键增强协议，以便区分 Shift+Enter
+    let keyboard_enhanced = supports_keyboard_enhancement().unwrap_or(false);
+    if keyboard_enhanced {
+        io::stdout().execute(PushKeyboardEnhancementFlags(KeyboardEnhancementFlags::DISAMBIGUATE_ESCAPE_CODES))?;
+    }
     info!("终端已初始化");
 
     info!("进入主循环");
@@ -70,6 +155,11 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     }
 
     // --- 恢复终端 ---
+    if keyboard_enhanced {
+        io::stdout().execute(PopKeyboardEnhancementFlags)?;
+    }
+    io::stdout().execute(DisableMouseCapture)?;
+    io::stdout().execute(DisableBracketedPaste)?;
     disable_raw_mode()?;
     io::stdout().execute(LeaveAlternateScreen)?;
     info!("恢复终端");
@@ -0,0 +1,365 @@
+use pulldown_cmark::{CodeBlockKind, Event, HeadingLevel, Options, Parser, Tag, TagEnd};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span};
+use unicode_width::UnicodeWidthStr;
+use crate::highlight::{self, StyledLine};
+
+const CODE_BG: Color = Color::Indexed(236);
+const QUOTE_PREFIX: &str = "│ ";
+
+/*
+ * -------- [ Markdown -> ratatui 渲染 ] --------
+ * - 支持 粗体/斜体/删除线/行内代码、标题、列表、引用、表格、代码块
+ * - 工具代码块（exec / read / diff / switch）渲染为带标签的卡片
+ * - 对话中的单个换行视为硬换行，保留模型输出的排版
+ */
+pub fn render_markdown(text: &str, base: Style) -> Vec<Line<'static>> {
+    let options = Options::ENABLE_TABLES | Options::ENABLE_STRIKETHROUGH;
+    let mut renderer = Renderer::new(base);
+    for event in Parser::new_ext(text, options) {
+        renderer.handle(event);
+    }
+    renderer.finish()
+}
+
+struct CodeBlock {
+    info: String,
+    text: String,
+}
+
+struct Table {
+    rows: Vec<Vec<String>>,
+    cell: String,
+    header_rows: usize,
+}
+
+struct Renderer {
+    lines: Vec<Line<'static>>,
+    spans: Vec<Span<'static>>,
+    styles: Vec<Style>,
+    list_stack: Vec<Option<u64>>,
+    quote_depth: usize,
+    code: Option<CodeBlock>,
+    table: Option<Table>,
+}
+
+impl Renderer {
+    fn new(base: Style) -> Self {
+        Self {
+            lines: Vec::new(),
+            spans: Vec::new(),
+            styles: vec![base],
+            list_stack: Vec::new(),
+            quote_depth: 0,
+            code: None,
+            table: None,
+        }
+    }
+
+    fn style(&self) -> Style {
+        *self.styles.last().unwrap()
+    }
+
+    fn push_style(&mut self, patch: Style) {
+        self.styles.push(self.style().patch(patch));
+    }
+
+    /// 当前行为空时先补上引用前缀
+    fn push_span(&mut self, span: Span<'static>) {
+        if self.spans.is_empty() && self.quote_depth > 0 {
+            let prefix = QUOTE_PREFIX.repeat(self.quote_depth);
+            self.spans.push(Span::styled(prefix, Style::default().fg(Color::DarkGray)));
+        }
+        self.spans.push(span);
+    }
+
+    fn flush_line(&mut self) {
+        if !self.spans.is_empty() {
+            self.lines.push(Line::from(std::mem::take(&mut self.spans)));
+        }
+    }
+
+    /// 块级元素之间留一空行（列表内部除外）
+    fn end_block(&mut self) {
+        self.flush_line();
+        if self.list_stack.is_empty() && self.lines.last().is_some_and(|l| !l.spans.is_empty()) {
+            self.lines.push(Line::from(""));
+        }
+    }
+
+    fn handle(&mut self, event: Event) {
+        if let Some(table) = &mut self.table {
+            match event {
+                Event::Text(t) | Event::Code(t) => { table.cell.push_str(&t); return }
+                Event::End(TagEnd::TableCell) => {
+                    let cell = std::mem::take(&mut table.cell);
+                    table.rows.last_mut().unwrap().push(cell.trim().to_string());
+                    return
+                }
+                Event::Start(Tag::TableHead) | Event::Start(Tag::TableRow) => {
+                    table.rows.push(Vec::new());
+                    return
+                }
+                Event::End(TagEnd::TableHead) => { table.header_rows = table.rows.len(); return }
+                Event::End(TagEnd::Table) => {}
+                _ => return
+            }
+        }
+
+        if let Some(code) = &mut self.code {
+            match event {
+                Event::Text(t) => { code.text.push_str(&t); return }
+                Event::End(TagEnd::CodeBlock) => {}
+                _ => return
+            }
+        }
+
+        match event {
+            Event::Start(tag) => self.start(tag),
+            Event::End(tag) => self.end(tag),
+            Event::Text(t) => {
+                let style = self.style();
+                let mut parts = t.split('\n').peekable();
+                while let Some(part) = parts.next() {
+                    if !part.is_empty() {
+                        self.push_span(Span::styled(part.to_string(), style));
+                    }
+                    if parts.peek().is_some() {
+                        self.flush_line();
+                    }
+                }
+            }
+            Event::Code(t) => {
+                let style = self.style().patch(Style::default().fg(Color::Yellow).bg(CODE_BG));
+                self.push_span(Span::styled(t.to_string(), style));
+            }
+            Event::SoftBreak | Event::HardBreak => self.flush_line(),
+            Event::Rule => {
+                self.flush_line();
+                self.lines.push(Line::styled("─".repeat(40), Style::default().fg(Color::DarkGray)));
+                self.end_block();
+            }
+            Event::Html(t) | Event::InlineHtml(t) => {
+                let style = self.style();
+                self.push_span(Span::styled(t.trim_end().to_string(), style));
+            }
+            Event::TaskListMarker(done) => {
+                let style = self.style();
+                self.push_span(Span::styled(if done { "[x] " } else { "[ ] " }, style));
+            }
+            _ => {}
+        }
+    }
+
+    fn start(&mut self, tag: Tag) {
+        match tag {
+            Tag::Heading { level, .. } => {
+                self.flush_line();
+                let style = match level {
+                    HeadingLevel::H1 => Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD | Modifier::UNDERLINED),
+                    HeadingLevel::H2 => Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD),
+                    _ => Style::default().add_modifier(Modifier::BOLD),
+                };
+                self.push_style(style);
+            }
+            Tag::Emphasis => self.push_style(Style::default().add_modifier(Modifier::ITALIC)),
+            Tag::Strong => self.push_style(Style::default().add_modifier(Modifier::BOLD)),
+            Tag::Strikethrough => self.push_style(Style::default().add_modifier(Modifier::CROSSED_OUT)),
+            Tag::Link { .. } => self.push_style(Style::default().fg(Color::Blue).add_modifier(Modifier::UNDERLINED)),
+            Tag::BlockQuote(_) => {
+                self.flush_line();
+                self.quote_depth += 1;
+                self.push_style(Style::default().add_modifier(Modifier::ITALIC));
+            }
+            Tag::List(start) => {
+                self.flush_line();
+                self.list_stack.push(start);
+            }
+            Tag::Item => {
+                self.flush_line();
+                let indent = "  ".repeat(self.list_stack.len().saturating_sub(1));
+                let bullet = match self.list_stack.last_mut() {
+                    Some(Some(n)) => { *n += 1; format!("{}{}. ", indent, *n - 1) }
+                    _ => format!("{}• ", indent),
+                };
+                self.push_span(Span::styled(bullet, Style::default().fg(Color::DarkGray)));
+            }
+            Tag::CodeBlock(kind) => {
+                self.flush_line();
+                let info = match kind {
+                    CodeBlockKind::Fenced(info) => info.trim().to_string(),
+                    CodeBlockKind::Indented => String::new(),
+                };
+                self.code = Some(CodeBlock { info, text: String::new() });
+            }
+            Tag::Table(_) => {
+                self.flush_line();
+                self.table = Some(Table { rows: Vec::new(), cell: String::new(), header_rows: 0 });
+            }
+            _ => {}
+        }
+    }
+
+    fn end(&mut self, tag: TagEnd) {
+        match tag {
+            TagEnd::Heading(_) => {
+                self.styles.pop();
+                self.end_block();
+            }
+            TagEnd::Emphasis | TagEnd::Strong | TagEnd::Strikethrough | TagEnd::Link => {
+                self.styles.pop();
+            }
+            TagEnd::Paragraph => self.end_block(),
+            TagEnd::BlockQuote(_) => {
+                self.flush_line();
+                self.styles.pop();
+                self.quote_depth -= 1;
+                if self.quote_depth == 0 {
+                    self.end_block();
+                }
+            }
+            TagEnd::List(_) => {
+                self.list_stack.pop();
+                self.end_block();
+            }
+            TagEnd::Item => self.flush_line(),
+            TagEnd::CodeBlock => {
+                let code = self.code.take().unwrap();
+                let lines = match tool_label(&code.info) {
+                    Some((label, color)) => render_tool_card(label, color, &code.info, &code.text),
+                    None => render_code_block(&code.info, &code.text),
+                };
+                self.lines.extend(lines);
+                self.end_block();
+            }
+            TagEnd::Table => {
+                let table = self.table.take().unwrap();
+                self.lines.extend(render_table(&table));
+                self.end_block();
+            }
+            _ => {}
+        }
+    }
+
+    fn finish(mut self) -> Vec<Line<'static>> {
+        self.flush_line();
+        if let Some(code) = self.code.take() {
+            // 流式输出中尚未闭合的代码块
+            self.lines.extend(render_code_block(&code.info, &code.text));
+        }
+        while self.lines.last().is_some_and(|l| l.spans.is_empty()) {
+            self.lines.pop();
+        }
+        self.lines
+    }
+}
+
+/// 工具代码块的标签与颜色，info 形如 `exec`、`read:src/main.rs`、`diff:src/app.rs`
+fn tool_label(info: &str) -> Option<(&'static str, Color)> {
+    let tool = info.split(':').next().unwrap_or("").trim();
+    match tool {
+        "exec" => Some(("EXEC", Color::Yellow)),
+        "read" => Some(("READ", Color::Blue)),
+        "diff" => Some(("DIFF", Color::Magenta)),
+        "switch" => Some(("SWITCH", Color::Green)),
+        _ => None,
+    }
+}
+
+fn render_tool_card(label: &str, color: Color, info: &str, text: &str) -> Vec<Line<'static>> {
+    let border = Style::default().fg(color);
+    let target = info.split_once(':').map(|(_, t)| t.trim().trim_matches('"')).unwrap_or("");
+
+    let mut header = vec![
+        Span::styled("╭─ ", border),
+        Span::styled(label.to_string(), border.add_modifier(Modifier::BOLD)),
+    ];
+    if !target.is_empty() {
+        header.push(Span::styled(format!(" {}", target), Style::default().fg(Color::White)));
+    }
+    header.push(Span::styled(" ─", border));
+
+    let mut lines = vec![Line::from(header)];
+    if label == "READ" && !text.is_empty() {
+        // read 结果：按扩展名高亮，保留绝对行号
+        for spans in highlight::highlight_numbered(target, text) {
+            let mut line = vec![Span::styled("│ ", border)];
+            line.extend(spans);
+            lines.push(Line::from(line));
+        }
+        lines.push(Line::styled("╰─", border));
+        return lines
+    }
+
+    for line in text.lines() {
+        let style = match label {
+            "DIFF" if line.starts_with('+') && !line.starts_with("+++") => Style::default().fg(Color::Green),
+            "DIFF" if line.starts_with('-') && !line.starts_with("---") => Style::default().fg(Color::Red),
+            "DIFF" if line.starts_with("@@") => Style::default().fg(Color::Cyan),
+            _ => Style::default(),
+        };
+        lines.push(Line::from(vec![
+            Span::styled("│ ", border),
+            Span::styled(line.to_string(), style),
+        ]));
+    }
+    lines.push(Line::styled("╰─", border));
+    lines
+}
+
+/// 普通代码块：按语言高亮，统一背景色，宽度对齐到最长行
+fn render_code_block(lang: &str, text: &str) -> Vec<Line<'static>> {
+    let code_lines: Vec<StyledLine> = highlight::highlight_code(lang, text)
+        .unwrap_or_else(|| text.lines().map(|l| vec![Span::raw(l.to_string())]).collect());
+    let bg = highlight::background().unwrap_or(CODE_BG);
+    let width = text.lines().map(UnicodeWidthStr::width).max().unwrap_or(0) + 2;
+
+    let mut lines = Vec::new();
+    if !lang.is_empty() {
+        lines.push(Line::styled(format!(" {} ", lang), Style::default().fg(Color::DarkGray).bg(bg)));
+    }
+    for spans in code_lines {
+        let line_width: usize = spans.iter().map(|s| s.content.width()).sum();
+        let mut line = vec![Span::raw(" ")];
+        line.extend(spans);
+        line.push(Span::raw(" ".repeat(width - 1 - line_width)));
+        lines.push(Line::from(line).style(Style::default().bg(bg)));
+    }
+    lines
+}
+
+fn render_table(table: &Table) -> Vec<Line<'static>> {
+    let columns = table.rows.iter().map(Vec::len).max().unwrap_or(0);
+    let widths: Vec<usize> = (0..columns)
+        .map(|i| table.rows.iter().filter_map(|r| r.get(i)).map(|c| c.width()).max().unwrap_or(0))
+        .collect();
+    let border = Style::default().fg(Color::DarkGray);
+
+    let separator = |left: &str, mid: &str, right: &str| {
+        let inner: Vec<String> = widths.iter().map(|w| "─".repeat(w + 2)).collect();
+        Line::styled(format!("{}{}{}", left, inner.join(mid), right), border)
+    };
+
+    let mut lines = vec![separator("┌", "┬", "┐")];
+    for (i, row) in table.rows.iter().enumerate() {
+        let cell_style = if i < table.header_rows {
+            Style::default().add_modifier(Modifier::BOLD)
+        } else {
+            Style::default()
+        };
+
+        let mut spans = vec![Span::styled("│", border)];
+        for (col, width) in widths.iter().enumerate() {
+            let cell = row.get(col).map(String::as_str).unwrap_or("");
+            spans.push(Span::styled(format!(" {}{} ", cell, " ".repeat(width - cell.width())), cell_style));
+            spans.push(Span::styled("│", border));
+        }
+        lines.push(Line::from(spans));
+
+        if i + 1 == table.header_rows {
+            lines.push(separator("├", "┼", "┤"));
+        }
+    }
+    lines.push(separator("└", "┴", "┘"));
+    lines
+}
@@ -0,0 +1,122 @@
+use std::fs;
+use serde::{Deserialize, Serialize};
+use crate::app::{ChatMessage, Model};
+use crate::redact::redact;
+
+// 单个固定文件写入上下文的字节上限
+const PIN_FILE_MAX_BYTES: usize = 32 * 1024;
+
+const PINS_HEADER: &str = "[固定上下文] 以下内容由用户固定，在整个会话中有效；文件在每次请求前重新读取，始终为最新内容。";
+
+/// 固定的上下文条目
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+#[serde(tag = "kind", content = "value", rename_all = "lowercase")]
+pub enum Pin {
+    File(String),
+    Text(String),
+}
+
+impl Pin {
+    /// `/pins` 列表中的一行描述
+    fn describe(&self) -> String {
+        match self {
+            Pin::File(path) => format!("文件 <{}>", path),
+            Pin::Text(text) => {
+                let preview: String = text.chars().take(60).collect();
+                let ellipsis = if text.chars().count() > 60 { "..." } else { "" };
+                format!("文本 \"{}{}\"", preview, ellipsis)
+            }
+        }
+    }
+
+    /// 写入上下文的内容；文件每次都从磁盘重新读取
+    fn render(&self) -> String {
+        match self {
+            Pin::File(path) => match fs::read_to_string(path) {
+                Ok(mut content) => {
+                    if content.len() > PIN_FILE_MAX_BYTES {
+                        let mut end = PIN_FILE_MAX_BYTES;
+                        while !content.is_char_boundary(end) {
+                            end -= 1;
+                        }
+                        content.truncate(end);
+                        content.push_str(&format!("\n... [已截断：超过 {} 字节] ...", PIN_FILE_MAX_BYTES));
+                    }
+                    format!("### 文件 {}\n```\n{}\n```", path, content.trim_end())
+                }
+                Err(e) => format!("### 文件 {}\n（无法读取: {}）", path, e),
+            },
+            Pin::Text(text) => format!("### 备注\n{}", text),
+        }
+    }
+}
+
+/*
+ * -------- [ 固定上下文 ] --------
+ * 按角色保存用户固定的文件与文本：
+ * - 不写入对话历史，发送请求时作为一条 system 消息插入在系统提示词之后
+ * - 因此不受上下文压缩、/clear 与 /undo 影响，随会话一同保存
+ */
+#[derive(Serialize, Deserialize, Default)]
+pub struct Pins {
+    // 按 Model::ALL 顺序
+    roles: [Vec<Pin>; 4],
+}
+
+impl Pins {
+    pub fn add(&mut self, model: Model, pin: Pin) -> Result<String, String> {
+        let pins = &mut self.roles[model.index()];
+        if pins.contains(&pin) {
+            return Err(format!("{} 已固定{}", model.name(), pin.describe()))
+        }
+        let message = format!("已为 {} 固定{}", model.name(), pin.describe());
+        pins.push(pin);
+        Ok(message)
+    }
+
+    /// 按 `/pins` 中的序号（从 1 开始）移除，None 时全部移除
+    pub fn remove(&mut self, model: Model, index: Option<usize>) -> Result<String, String> {
+        let pins = &mut self.roles[model.index()];
+        match index {
+            None => {
+                let count = pins.len();
+                pins.clear();
+                Ok(format!("已移除 {} 的全部 {} 项固定上下文", model.name(), count))
+            }
+            Some(i) if (1..=pins.len()).contains(&i) => {
+                let pin = pins.remove(i - 1);
+                Ok(format!("已移除 {} 的固定{}", model.name(), pin.describe()))
+            }
+            Some(i) => Err(format!("{} 没有第 {} 项固定上下文", model.name(), i)),
+        }
+    }
+
+    pub fn list(&self, model: Model) -> String {
+        let pins = &self.roles[model.index()];
+        if pins.is_empty() {
+            return format!("{} 没有固定上下文", model.name())
+        }
+        let mut output = format!("{} 的固定上下文:", model.name());
+        for (i, pin) in pins.iter().enumerate() {
+            output.push_str(&format!("\n  #{} {}", i + 1, pin.describe()));
+        }
+        output
+    }
+
+    /// 组装角色的固定上下文消息，没有固定条目时为 None
+    pub fn message(&self, model: Model) -> Option<ChatMessage> {
+        let pins = &self.roles[model.index()];
+        if pins.is_empty() {
+            return None
+        }
+        let sections: Vec<String> = pins.iter().map(Pin::render).collect();
+        let content = format!("{}\n\n{}", PINS_HEADER, sections.join("\n\n"));
+        Some(ChatMessage { role: "system".into(), content: redact(&content) })
+    }
+}
+
+/// 将固定上下文插入在系统提示词之后（BALTHAZAR 没有系统提示词，插入在开头）
+pub fn insert(model: Model, messages: &mut Vec<ChatMessage>, pinned: ChatMessage) {
+    let head = usize::from(model != Model::BALTHAZAR && messages.first().is_some_and(|m| m.role == "system"));
+    messages.insert(head, pinned);
+}
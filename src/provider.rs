@@ -0,0 +1,611 @@
+use reqwest::blocking::RequestBuilder;
+use reqwest::header::AUTHORIZATION;
+use serde::Serialize;
+use serde_json::{json, Map, Value};
+use crate::app::ChatMessage;
+use crate::config_manager::{get_config, ProviderKind, RoleConfig};
+
+// Anthropic 要求必须指定 max_tokens，未配置时使用该值
+const ANTHROPIC_DEFAULT_MAX_TOKENS: u32 = 4096;
+const ANTHROPIC_VERSION: &str = "2023-06-01";
+
+/// 流式响应中解析出的事件
+#[derive(Debug, PartialEq)]
+pub enum StreamEvent {
+    Text(String),
+    // 思考过程，不计入回复正文
+    Reasoning(String),
+    Usage(Usage),
+    Error(String),
+    Done,
+}
+
+/// token 用量；部分接口分多次上报，未上报的字段为 None
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Usage {
+    pub prompt_tokens: Option<u64>,
+    pub completion_tokens: Option<u64>,
+}
+
+impl Usage {
+    /// 合并同一请求中分次上报的用量，后上报的值优先
+    pub fn merge(&mut self, other: Usage) {
+        self.prompt_tokens = other.prompt_tokens.or(self.prompt_tokens);
+        self.completion_tokens = other.completion_tokens.or(self.completion_tokens);
+    }
+}
+
+/// 构造请求所需的参数
+pub struct ChatParams<'a> {
+    pub role: &'a RoleConfig,
+    pub messages: Vec<ChatMessage>,
+    // 覆盖 role.max_tokens，用于探测等短请求
+    pub max_tokens: Option<u32>,
+    pub context_window: usize,
+    // 是否请求流式用量统计（provider.stream_usage）
+    pub stream_usage: bool,
+}
+
+impl ChatParams<'_> {
+    fn max_tokens(&self) -> Option<u32> {
+        self.max_tokens.or(self.role.max_tokens)
+    }
+}
+
+/*
+ * -------- [ Provider 适配器 ] --------
+ * 每种接口格式实现一个适配器，负责：
+ * - 认证方式
+ * - 请求体构造与角色映射（system 提示词、连续的 system 消息）
+ * - 逐行解析流式响应（SSE 或 NDJSON）
+ */
+pub trait Adapter {
+    fn auth(&self, request: RequestBuilder, api_key: &str) -> RequestBuilder {
+        // 本地服务通常无需密钥
+        if api_key.is_empty() {
+            request
+        } else {
+            request.header(AUTHORIZATION, format!("Bearer {}", api_key))
+        }
+    }
+
+    fn body(&self, params: ChatParams) -> Value;
+
+    /// 解析流式响应中的一行，可能产生零个或多个事件
+    fn parse_line(&self, line: &str) -> Vec<StreamEvent>;
+}
+
+impl ProviderKind {
+    pub fn adapter(&self) -> &'static dyn Adapter {
+        match self {
+            ProviderKind::OpenAI | ProviderKind::Ollama => &OpenAIAdapter,
+            ProviderKind::OllamaNative => &OllamaAdapter,
+            ProviderKind::Anthropic => &AnthropicAdapter,
+        }
+    }
+}
+
+/// extra 平铺进请求体
+fn merge_extra(body: &mut Value, extra: &Map<String, Value>) {
+    if let Value::Object(map) = body {
+        map.extend(extra.iter().map(|(k, v)| (k.clone(), v.clone())));
+    }
+}
+
+/* -------- [ OpenAI 兼容接口 ] -------- */
+struct OpenAIAdapter;
+
+#[derive(Serialize)]
+struct ChatRequest<'a> {
+    model: &'a str,
+    messages: Vec<ChatMessage>,
+    stream: bool, // 虽然是同步线程，我们依然可以用流式处理
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_p: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_tokens: Option<u32>,
+    #[serde(skip_serializing_if = "<[String]>::is_empty")]
+    stop: &'a [String],
+    #[serde(skip_serializing_if = "Option::is_none")]
+    seed: Option<u64>,
+    // 要求在最后一个片段中附带 usage；部分兼容服务不认识该字段，可按 provider 关闭
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stream_options: Option<StreamOptions>,
+    // provider 特有参数，平铺进请求体
+    #[serde(flatten)]
+    extra: &'a Map<String, Value>,
+}
+
+#[derive(Serialize)]
+struct StreamOptions {
+    include_usage: bool,
+}
+
+impl Adapter for OpenAIAdapter {
+    fn body(&self, params: ChatParams) -> Value {
+        let max_tokens = params.max_tokens();
+        let role = params.role;
+        let request = ChatRequest {
+            model: &role.model,
+            messages: params.messages,
+            stream: true,
+            temperature: role.temperature,
+            top_p: role.top_p,
+            max_tokens,
+            stop: &role.stop,
+            seed: role.seed,
+            stream_options: params.stream_usage.then_some(StreamOptions { include_usage: true }),
+            extra: &role.extra,
+        };
+        serde_json::to_value(request).expect("ChatRequest -> JSON 转换错误")
+    }
+
+    fn parse_line(&self, line: &str) -> Vec<StreamEvent> {
+        let Some(data) = line.strip_prefix("data:").map(str::trim) else { return Vec::new() };
+        if data == "[DONE]" {
+            return vec![StreamEvent::Done]
+        }
+        let Ok(json) = serde_json::from_str::<Value>(data) else { return Vec::new() };
+
+        let mut events = Vec::new();
+        if let Some(message) = json["error"]["message"].as_str() {
+            events.push(StreamEvent::Error(message.to_string()));
+        }
+        let delta = &json["choices"][0]["delta"];
+        // 不同服务的思考字段名不同：reasoning_content（DeepSeek、vLLM 等）或 reasoning（Ollama 等）
+        if let Some(reasoning) = delta["reasoning_content"].as_str().or(delta["reasoning"].as_str())
+            && !reasoning.is_empty() {
+            events.push(StreamEvent::Reasoning(reasoning.to_string()));
+        }
+        if let Some(content) = delta["content"].as_str() {
+            events.push(StreamEvent::Text(content.to_string()));
+        }
+        // include_usage 时最后一个片段附带 usage（choices 为空）
+        if json["usage"].is_object() {
+            events.push(StreamEvent::Usage(Usage {
+                prompt_tokens: json["usage"]["prompt_tokens"].as_u64(),
+                completion_tokens: json["usage"]["completion_tokens"].as_u64(),
+            }));
+        }
+        events
+    }
+}
+
+/*
+ * -------- [ Ollama 原生接口 ] --------
+ * POST /api/chat，响应为 NDJSON；采样参数放在 options 中，并以 context_window 设置 num_ctx
+ */
+struct OllamaAdapter;
+
+impl Adapter for OllamaAdapter {
+    fn body(&self, params: ChatParams) -> Value {
+        let role = params.role;
+        let mut options = Map::new();
+        options.insert("num_ctx".into(), json!(params.context_window));
+        if let Some(t) = role.temperature { options.insert("temperature".into(), json!(t)); }
+        if let Some(p) = role.top_p { options.insert("top_p".into(), json!(p)); }
+        if let Some(n) = params.max_tokens() { options.insert("num_predict".into(), json!(n)); }
+        if !role.stop.is_empty() { options.insert("stop".into(), json!(role.stop)); }
+        if let Some(s) = role.seed { options.insert("seed".into(), json!(s)); }
+
+        // extra.options 与上面的 options 合并，其余字段平铺
+        let mut extra = role.extra.clone();
+        if let Some(Value::Object(custom)) = extra.remove("options") {
+            options.extend(custom);
+        }
+
+        let mut body = json!({
+            "model": role.model,
+            "messages": params.messages,
+            "stream": true,
+            "options": options,
+        });
+        merge_extra(&mut body, &extra);
+        body
+    }
+
+    fn parse_line(&self, line: &str) -> Vec<StreamEvent> {
+        let Ok(json) = serde_json::from_str::<Value>(line.trim()) else { return Vec::new() };
+
+        if let Some(error) = json["error"].as_str() {
+            return vec![StreamEvent::Error(error.to_string())]
+        }
+        let mut events = Vec::new();
+        if let Some(thinking) = json["message"]["thinking"].as_str()
+            && !thinking.is_empty() {
+            events.push(StreamEvent::Reasoning(thinking.to_string()));
+        }
+        if let Some(content) = json["message"]["content"].as_str()
+            && !content.is_empty() {
+            events.push(StreamEvent::Text(content.to_string()));
+        }
+        if json["done"].as_bool() == Some(true) {
+            events.push(StreamEvent::Usage(Usage {
+                prompt_tokens: json["prompt_eval_count"].as_u64(),
+                completion_tokens: json["eval_count"].as_u64(),
+            }));
+            events.push(StreamEvent::Done);
+        }
+        events
+    }
+}
+
+/*
+ * -------- [ Anthropic Messages 接口 ] --------
+ * - 开头的 system 消息合并为顶层 system 字段
+ * - 之后的 system 消息（工具结果等）作为 user 消息发送
+ * - 接口要求 user / assistant 交替出现且以 user 开头，相邻同角色消息合并
+ */
+struct AnthropicAdapter;
+
+impl AnthropicAdapter {
+    fn map_messages(messages: Vec<ChatMessage>) -> (String, Vec<ChatMessage>) {
+        let mut system = Vec::new();
+        let mut mapped: Vec<ChatMessage> = Vec::new();
+
+        for msg in messages {
+            if msg.role == "system" && mapped.is_empty() {
+                system.push(msg.content);
+                continue
+            }
+            let role = if msg.role == "assistant" { "assistant" } else { "user" };
+            match mapped.last_mut() {
+                Some(last) if last.role == role => {
+                    last.content.push_str("\n\n");
+                    last.content.push_str(&msg.content);
+                }
+                _ => mapped.push(ChatMessage { role: role.into(), content: msg.content }),
+            }
+        }
+        // 首条消息必须来自 user（如压缩后以保留的 spec 开头）
+        if mapped.first().is_some_and(|m| m.role == "assistant") {
+            mapped.insert(0, ChatMessage { role: "user".into(), content: "（继续之前的对话）".into() });
+        }
+        (system.join("\n\n"), mapped)
+    }
+}
+
+impl Adapter for AnthropicAdapter {
+    fn auth(&self, request: RequestBuilder, api_key: &str) -> RequestBuilder {
+        request
+            .header("x-api-key", api_key)
+            .header("anthropic-version", ANTHROPIC_VERSION)
+    }
+
+    fn body(&self, params: ChatParams) -> Value {
+        let max_tokens = params.max_tokens().unwrap_or(ANTHROPIC_DEFAULT_MAX_TOKENS);
+        let role = params.role;
+        let (system, messages) = Self::map_messages(params.messages);
+
+        let mut body = json!({
+            "model": role.model,
+            "messages": messages,
+            "max_tokens": max_tokens,
+            "stream": true,
+        });
+        if !system.is_empty() { body["system"] = json!(system); }
+        if let Some(t) = role.temperature { body["temperature"] = json!(t); }
+        if let Some(p) = role.top_p { body["top_p"] = json!(p); }
+        if !role.stop.is_empty() { body["stop_sequences"] = json!(role.stop); }
+        // Anthropic 不支持 seed
+        merge_extra(&mut body, &role.extra);
+        body
+    }
+
+    fn parse_line(&self, line: &str) -> Vec<StreamEvent> {
+        // `event:` 行与 data 中的 type 重复，只解析 data
+        let Some(data) = line.strip_prefix("data:").map(str::trim) else { return Vec::new() };
+        let Ok(json) = serde_json::from_str::<Value>(data) else { return Vec::new() };
+
+        match json["type"].as_str() {
+            Some("content_block_delta") => match json["delta"]["type"].as_str() {
+                Some("thinking_delta") => json["delta"]["thinking"].as_str()
+                    .map(|thinking| vec![StreamEvent::Reasoning(thinking.to_string())])
+                    .unwrap_or_default(),
+                _ => json["delta"]["text"].as_str()
+                    .map(|text| vec![StreamEvent::Text(text.to_string())])
+                    .unwrap_or_default(),
+            },
+            Some("message_start") => vec![StreamEvent::Usage(Usage {
+                prompt_tokens: json["message"]["usage"]["input_tokens"].as_u64(),
+                completion_tokens: None,
+            })],
+            Some("message_delta") => vec![StreamEvent::Usage(Usage {
+                prompt_tokens: None,
+                completion_tokens: json["usage"]["output_tokens"].as_u64(),
+            })],
+            Some("message_stop") => vec![StreamEvent::Done],
+            Some("error") => vec![StreamEvent::Error(
+                json["error"]["message"].as_str().unwrap_or("未知错误").to_string()
+            )],
+            _ => Vec::new(),
+        }
+    }
+}
+
+/*
+ * -------- [ <think> 标签拆分 ] --------
+ * qwen3 等模型把思考过程以 `<think>...</think>` 写在正文开头；
+ * 只有回复的第一段非空白内容是 `<think>` 时才进入思考过程，正文中（如代码块里）的标签原样输出；
+ * 标签可能被拆在两个片段之间，因此保留疑似标签开头的尾部，等待下一个片段
+ */
+pub struct ThinkSplitter {
+    state: ThinkState,
+    buffer: String,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum ThinkState {
+    // 尚未收到非空白内容
+    Start,
+    Reasoning,
+    Answer,
+}
+
+impl ThinkSplitter {
+    const OPEN: &'static str = "<think>";
+    const CLOSE: &'static str = "</think>";
+
+    pub fn new() -> Self {
+        Self { state: ThinkState::Start, buffer: String::new() }
+    }
+
+    /// 拆分一个正文片段，返回 Text / Reasoning 事件
+    pub fn push(&mut self, chunk: &str) -> Vec<StreamEvent> {
+        self.buffer.push_str(chunk);
+        let mut events = Vec::new();
+
+        loop {
+            match self.state {
+                ThinkState::Start => {
+                    let trimmed = self.buffer.trim_start();
+                    if trimmed.starts_with(Self::OPEN) {
+                        let skip = self.buffer.len() - trimmed.len() + Self::OPEN.len();
+                        self.buffer.drain(..skip);
+                        self.state = ThinkState::Reasoning;
+                        continue
+                    }
+                    // 可能是被拆开的 <think>，等待下一个片段
+                    if Self::OPEN.starts_with(trimmed) {
+                        return events
+                    }
+                    self.state = ThinkState::Answer;
+                }
+                ThinkState::Reasoning => {
+                    if let Some(pos) = self.buffer.find(Self::CLOSE) {
+                        let before = self.buffer[..pos].to_string();
+                        self.buffer.drain(..pos + Self::CLOSE.len());
+                        self.emit(before, &mut events);
+                        self.state = ThinkState::Answer;
+                        continue
+                    }
+                    let keep = (1..Self::CLOSE.len()).rev()
+                        .find(|&n| self.buffer.ends_with(&Self::CLOSE[..n]))
+                        .unwrap_or(0);
+                    let ready = self.buffer.drain(..self.buffer.len() - keep).collect();
+                    self.emit(ready, &mut events);
+                    return events
+                }
+                ThinkState::Answer => {
+                    let rest = std::mem::take(&mut self.buffer);
+                    self.emit(rest, &mut events);
+                    return events
+                }
+            }
+        }
+    }
+
+    /// 流结束时输出剩余内容
+    pub fn finish(&mut self) -> Vec<StreamEvent> {
+        let mut events = Vec::new();
+        let rest = std::mem::take(&mut self.buffer);
+        self.emit(rest, &mut events);
+        events
+    }
+
+    fn emit(&self, text: String, events: &mut Vec<StreamEvent>) {
+        if text.is_empty() {
+            return
+        }
+        events.push(if self.state == ThinkState::Reasoning { StreamEvent::Reasoning(text) } else { StreamEvent::Text(text) });
+    }
+}
+
+/// 写入上下文的回复内容：keep_reasoning 开启时以 `<think>` 标签附带思考过程，与模型原始输出格式一致
+pub fn with_reasoning(content: &str, reasoning: &str) -> String {
+    if get_config().read().unwrap().keep_reasoning && !reasoning.trim().is_empty() {
+        format!("<think>\n{}\n</think>\n\n{}", reasoning.trim(), content)
+    } else {
+        content.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 依次喂入片段，返回 (正文, 思考过程)
+    fn split(chunks: &[&str]) -> (String, String) {
+        let mut splitter = ThinkSplitter::new();
+        let mut events: Vec<StreamEvent> = chunks.iter().flat_map(|c| splitter.push(c)).collect();
+        events.extend(splitter.finish());
+        let (mut text, mut reasoning) = (String::new(), String::new());
+        for event in events {
+            match event {
+                StreamEvent::Text(t) => text.push_str(&t),
+                StreamEvent::Reasoning(r) => reasoning.push_str(&r),
+                _ => panic!("意外的事件"),
+            }
+        }
+        (text, reasoning)
+    }
+
+    #[test]
+    fn think_splitter_extracts_leading_block() {
+        let (text, reasoning) = split(&["\n<think>想一想</think>答案"]);
+        assert_eq!(reasoning, "想一想");
+        assert_eq!(text, "答案");
+    }
+
+    #[test]
+    fn think_splitter_handles_tags_split_across_chunks() {
+        let (text, reasoning) = split(&["<th", "ink>abc</th", "in", "k>def"]);
+        assert_eq!(reasoning, "abc");
+        assert_eq!(text, "def");
+
+        let (text, reasoning) = split(&[" ", "<", "think", ">x<", "/think>", "y"]);
+        assert_eq!(reasoning, "x");
+        assert_eq!(text, "y");
+    }
+
+    #[test]
+    fn think_splitter_ignores_tags_after_answer_starts() {
+        let (text, reasoning) = split(&["示例：\n```\n<think>", "不是思考</think>\n```"]);
+        assert_eq!(reasoning, "");
+        assert_eq!(text, "示例：\n```\n<think>不是思考</think>\n```");
+
+        // 只有第一个块是思考过程
+        let (text, reasoning) = split(&["<think>a</think>b<think>c</think>"]);
+        assert_eq!(reasoning, "a");
+        assert_eq!(text, "b<think>c</think>");
+    }
+
+    #[test]
+    fn think_splitter_flushes_partial_prefix_on_finish() {
+        let (text, reasoning) = split(&["<thi"]);
+        assert_eq!(reasoning, "");
+        assert_eq!(text, "<thi");
+
+        let (text, reasoning) = split(&["<think>未闭合"]);
+        assert_eq!(reasoning, "未闭合");
+        assert_eq!(text, "");
+    }
+
+    fn message(role: &str, content: &str) -> ChatMessage {
+        ChatMessage { role: role.into(), content: content.into() }
+    }
+
+    fn usage(prompt_tokens: Option<u64>, completion_tokens: Option<u64>) -> StreamEvent {
+        StreamEvent::Usage(Usage { prompt_tokens, completion_tokens })
+    }
+
+    #[test]
+    fn openai_parses_sse_lines() {
+        let adapter = ProviderKind::OpenAI.adapter();
+        assert_eq!(
+            adapter.parse_line(r#"data: {"choices":[{"delta":{"reasoning_content":"想","content":"答"}}]}"#),
+            vec![StreamEvent::Reasoning("想".into()), StreamEvent::Text("答".into())],
+        );
+        assert_eq!(
+            adapter.parse_line(r#"data: {"choices":[],"usage":{"prompt_tokens":12,"completion_tokens":3}}"#),
+            vec![usage(Some(12), Some(3))],
+        );
+        assert_eq!(
+            adapter.parse_line(r#"data: {"error":{"message":"overloaded"}}"#),
+            vec![StreamEvent::Error("overloaded".into())],
+        );
+        assert_eq!(adapter.parse_line("data: [DONE]"), vec![StreamEvent::Done]);
+        // 注释、event 行与空行忽略
+        assert!(adapter.parse_line(": keep-alive").is_empty());
+        assert!(adapter.parse_line("").is_empty());
+    }
+
+    #[test]
+    fn openai_gates_stream_options() {
+        let role: RoleConfig = serde_json::from_value(json!({ "provider": "p", "model": "m" })).unwrap();
+        let body = |stream_usage| OpenAIAdapter.body(ChatParams {
+            role: &role,
+            messages: vec![message("user", "hi")],
+            max_tokens: None,
+            context_window: 4096,
+            stream_usage,
+        });
+        assert_eq!(body(true)["stream_options"], json!({ "include_usage": true }));
+        assert!(body(false).get("stream_options").is_none());
+    }
+
+    #[test]
+    fn ollama_parses_ndjson_lines() {
+        let adapter = ProviderKind::OllamaNative.adapter();
+        assert_eq!(
+            adapter.parse_line(r#"{"message":{"role":"assistant","thinking":"想","content":""},"done":false}"#),
+            vec![StreamEvent::Reasoning("想".into())],
+        );
+        assert_eq!(
+            adapter.parse_line(r#"{"message":{"role":"assistant","content":"答"},"done":false}"#),
+            vec![StreamEvent::Text("答".into())],
+        );
+        assert_eq!(
+            adapter.parse_line(r#"{"message":{"role":"assistant","content":""},"done":true,"prompt_eval_count":20,"eval_count":5}"#),
+            vec![usage(Some(20), Some(5)), StreamEvent::Done],
+        );
+        assert_eq!(
+            adapter.parse_line(r#"{"error":"model not found"}"#),
+            vec![StreamEvent::Error("model not found".into())],
+        );
+        assert!(adapter.parse_line("not json").is_empty());
+    }
+
+    #[test]
+    fn anthropic_parses_sse_lines() {
+        let adapter = ProviderKind::Anthropic.adapter();
+        assert!(adapter.parse_line("event: content_block_delta").is_empty());
+        assert_eq!(
+            adapter.parse_line(r#"data: {"type":"message_start","message":{"usage":{"input_tokens":30,"output_tokens":1}}}"#),
+            vec![usage(Some(30), None)],
+        );
+        assert_eq!(
+            adapter.parse_line(r#"data: {"type":"content_block_delta","index":0,"delta":{"type":"thinking_delta","thinking":"想"}}"#),
+            vec![StreamEvent::Reasoning("想".into())],
+        );
+        assert_eq!(
+            adapter.parse_line(r#"data: {"type":"content_block_delta","index":1,"delta":{"type":"text_delta","text":"答"}}"#),
+            vec![StreamEvent::Text("答".into())],
+        );
+        assert_eq!(
+            adapter.parse_line(r#"data: {"type":"message_delta","delta":{},"usage":{"output_tokens":7}}"#),
+            vec![usage(None, Some(7))],
+        );
+        assert_eq!(adapter.parse_line(r#"data: {"type":"message_stop"}"#), vec![StreamEvent::Done]);
+        assert_eq!(
+            adapter.parse_line(r#"data: {"type":"error","error":{"type":"overloaded_error","message":"Overloaded"}}"#),
+            vec![StreamEvent::Error("Overloaded".into())],
+        );
+        assert!(adapter.parse_line(r#"data: {"type":"ping"}"#).is_empty());
+    }
+
+    #[test]
+    fn anthropic_maps_system_and_merges_roles() {
+        let (system, mapped) = AnthropicAdapter::map_messages(vec![
+            message("system", "提示词"),
+            message("system", "固定上下文"),
+            message("user", "问题"),
+            message("assistant", "```read:a.rs\n```"),
+            message("system", "System: Read Result a.rs"),
+            message("system", "System: 第二条结果"),
+            message("assistant", "完成"),
+        ]);
+        assert_eq!(system, "提示词\n\n固定上下文");
+        let mapped: Vec<(&str, &str)> = mapped.iter().map(|m| (m.role.as_str(), m.content.as_str())).collect();
+        assert_eq!(mapped, vec![
+            ("user", "问题"),
+            ("assistant", "```read:a.rs\n```"),
+            ("user", "System: Read Result a.rs\n\nSystem: 第二条结果"),
+            ("assistant", "完成"),
+        ]);
+    }
+
+    #[test]
+    fn anthropic_starts_with_user() {
+        let (system, mapped) = AnthropicAdapter::map_messages(vec![
+            message("system", "提示词"),
+            message("assistant", "```spec\n- a.rs\n```"),
+            message("user", "继续"),
+        ]);
+        assert_eq!(system, "提示词");
+        let roles: Vec<&str> = mapped.iter().map(|m| m.role.as_str()).collect();
+        assert_eq!(roles, vec!["user", "assistant", "user"]);
+    }
+}
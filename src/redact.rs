@@ -0,0 +1,53 @@
+use std::io::{self, Write};
+use std::sync::RwLock;
+
+// 过短的字符串容易误伤正常文本，不作为密钥登记
+const MIN_SECRET_LEN: usize = 8;
+const MASK: &str = "[REDACTED]";
+
+static SECRETS: RwLock<Vec<String>> = RwLock::new(Vec::new());
+
+/*
+ * -------- [ 密钥脱敏 ] --------
+ * 配置载入时登记全部 API Key；日志、错误信息、工具结果与发给模型的上下文
+ * 在输出前经过 redact，出现的密钥原文替换为 [REDACTED]
+ */
+pub fn register_secrets(secrets: impl IntoIterator<Item = String>) {
+    let mut registered = SECRETS.write().unwrap();
+    registered.clear();
+    registered.extend(secrets.into_iter().filter(|s| s.len() >= MIN_SECRET_LEN));
+}
+
+/// 追加登记单个密钥（如首次使用时才解析的 api_key_cmd 结果）
+pub fn add_secret(secret: &str) {
+    let mut registered = SECRETS.write().unwrap();
+    if secret.len() >= MIN_SECRET_LEN && !registered.iter().any(|s| s == secret) {
+        registered.push(secret.to_string());
+    }
+}
+
+pub fn redact(text: &str) -> String {
+    let secrets = SECRETS.read().unwrap();
+    let mut output = text.to_string();
+    for secret in secrets.iter() {
+        if output.contains(secret.as_str()) {
+            output = output.replace(secret.as_str(), MASK);
+        }
+    }
+    output
+}
+
+/// 日志写入器：每条日志写入前脱敏
+pub struct RedactWriter<W: Write>(pub W);
+
+impl<W: Write> Write for RedactWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let text = String::from_utf8_lossy(buf);
+        self.0.write_all(redact(&text).as_bytes())?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.flush()
+    }
+}
@@ -0,0 +1,256 @@
+use std::io;
+use crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use crossterm::terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen};
+use crossterm::ExecutableCommand;
+use ratatui::backend::CrosstermBackend;
+use ratatui::layout::{Constraint, Direction, Layout};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Borders, Paragraph, Wrap};
+use ratatui::{Frame, Terminal};
+use tracing::info;
+use crate::api_client::list_models;
+use crate::config_manager::{service_root, Config, Provider, ProviderKind};
+
+// 需要分配模型的角色；CASPER-I 与 CASPER-II 共用一个模型
+const ROLES: [&str; 3] = ["MELCHIOR", "CASPER", "BALTHAZAR"];
+
+/*
+ * -------- [ 首次运行配置向导 ] --------
+ * 选择 provider 类型 -> 填写地址与密钥 -> 测试连接并列出模型 -> 为各角色分配模型 -> 保存
+ * - ESC 返回上一步，在第一步按 ESC 取消
+ * - 向导在 IO / Worker 线程启动前运行，自行进入与退出备用屏幕
+ */
+enum Step {
+    Kind { selected: usize },
+    Endpoint { focus: usize, error: Option<String> },
+    Assign { role: usize, selected: usize },
+    Review { error: Option<String> },
+}
+
+struct Wizard {
+    step: Step,
+    kind: ProviderKind,
+    root: String,
+    api_key: String,
+    models: Vec<String>,
+    assigned: Vec<String>,
+}
+
+/// 运行向导，返回是否已保存配置
+pub fn run() -> Result<bool, Box<dyn std::error::Error>> {
+    enable_raw_mode()?;
+    io::stdout().execute(EnterAlternateScreen)?;
+    let mut terminal = Terminal::new(CrosstermBackend::new(io::stdout()))?;
+
+    let result = Wizard::new().run(&mut terminal);
+
+    disable_raw_mode()?;
+    io::stdout().execute(LeaveAlternateScreen)?;
+    result
+}
+
+impl Wizard {
+    fn new() -> Self {
+        Self {
+            step: Step::Kind { selected: 0 },
+            kind: ProviderKind::ALL[0],
+            root: String::new(),
+            api_key: String::new(),
+            models: Vec::new(),
+            assigned: Vec::new(),
+        }
+    }
+
+    fn run(mut self, terminal: &mut Terminal<CrosstermBackend<io::Stdout>>) -> Result<bool, Box<dyn std::error::Error>> {
+        loop {
+            terminal.draw(|f| self.render(f, None))?;
+
+            let Event::Key(key) = event::read()? else { continue };
+            if key.kind == KeyEventKind::Release {
+                continue
+            }
+            if key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL) {
+                return Ok(false)
+            }
+
+            match &mut self.step {
+                Step::Kind { selected } => match key.code {
+                    KeyCode::Up => *selected = selected.saturating_sub(1),
+                    KeyCode::Down => *selected = (*selected + 1).min(ProviderKind::ALL.len() - 1),
+                    KeyCode::Enter => {
+                        self.kind = ProviderKind::ALL[*selected];
+                        self.root = self.kind.default_root().to_string();
+                        self.step = Step::Endpoint { focus: 0, error: None };
+                    }
+                    KeyCode::Esc => return Ok(false),
+                    _ => {}
+                },
+
+                Step::Endpoint { focus, error } => match key.code {
+                    KeyCode::Tab | KeyCode::Up | KeyCode::Down => *focus = 1 - *focus,
+                    KeyCode::Char(c) => {
+                        if *focus == 0 { self.root.push(c) } else { self.api_key.push(c) }
+                    }
+                    KeyCode::Backspace => {
+                        if *focus == 0 { self.root.pop(); } else { self.api_key.pop(); }
+                    }
+                    KeyCode::Enter => {
+                        *error = None;
+                        let root = service_root(&self.root);
+                        terminal.draw(|f| self.render(f, Some("正在连接并获取模型列表...")))?;
+                        match list_models(self.kind, &root, self.api_key.trim()) {
+                            Ok(models) if models.is_empty() => {
+                                self.step = Step::Endpoint { focus: 0, error: Some("连接成功，但没有可用模型".into()) };
+                            }
+                            Ok(models) => {
+                                info!("配置向导：<{}> 共 {} 个模型", root, models.len());
+                                self.root = root;
+                                self.models = models;
+                                self.assigned.clear();
+                                self.step = Step::Assign { role: 0, selected: 0 };
+                            }
+                            Err(e) => self.step = Step::Endpoint { focus: 0, error: Some(e) },
+                        }
+                    }
+                    KeyCode::Esc => self.step = Step::Kind { selected: 0 },
+                    _ => {}
+                },
+
+                Step::Assign { role, selected } => match key.code {
+                    KeyCode::Up => *selected = selected.saturating_sub(1),
+                    KeyCode::Down => *selected = (*selected + 1).min(self.models.len() - 1),
+                    KeyCode::Enter => {
+                        self.assigned.push(self.models[*selected].clone());
+                        if *role + 1 < ROLES.len() {
+                            *role += 1;
+                        } else {
+                            self.step = Step::Review { error: None };
+                        }
+                    }
+                    KeyCode::Esc => {
+                        if *role == 0 {
+                            self.step = Step::Endpoint { focus: 0, error: None };
+                        } else {
+                            *role -= 1;
+                            self.assigned.pop();
+                        }
+                    }
+                    _ => {}
+                },
+
+                Step::Review { .. } => match key.code {
+                    KeyCode::Enter => match self.config().save() {
+                        Ok(_) => return Ok(true),
+                        Err(e) => self.step = Step::Review { error: Some(e) },
+                    },
+                    KeyCode::Esc => {
+                        self.assigned.pop();
+                        self.step = Step::Assign { role: ROLES.len() - 1, selected: 0 };
+                    }
+                    _ => {}
+                },
+            }
+        }
+    }
+
+    fn config(&self) -> Config {
+        let name = match self.kind {
+            ProviderKind::OpenAI => "openai",
+            ProviderKind::Ollama => "ollama",
+            ProviderKind::OllamaNative => "ollama-native",
+            ProviderKind::Anthropic => "anthropic",
+        };
+        let provider = Provider::new(name, self.kind, self.kind.chat_url(&self.root), self.api_key.trim().to_string());
+        Config::template(provider, &self.assigned[0], &self.assigned[1], &self.assigned[2])
+    }
+
+    // --- [ 渲染 ] ---
+    fn render(&self, f: &mut Frame, busy: Option<&str>) {
+        let [title_area, body_area, hint_area] = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Length(3), Constraint::Min(5), Constraint::Length(1)])
+            .areas(f.area());
+
+        let (index, title) = match self.step {
+            Step::Kind { .. } => (1, "选择模型服务类型"),
+            Step::Endpoint { .. } => (2, "填写服务地址"),
+            Step::Assign { .. } => (3, "为角色分配模型"),
+            Step::Review { .. } => (4, "确认并保存"),
+        };
+        let header = Paragraph::new(Line::from(vec![
+            Span::styled(" Oxicodent 初始配置 ", Style::default().fg(Color::Black).bg(Color::Red).add_modifier(Modifier::BOLD)),
+            Span::raw(format!("  步骤 {}/4 · {}", index, title)),
+        ])).block(Block::default().borders(Borders::BOTTOM));
+        f.render_widget(header, title_area);
+
+        let selected_style = Style::default().fg(Color::Black).bg(Color::Cyan);
+        let mut lines = Vec::new();
+        let hint = match &self.step {
+            Step::Kind { selected } => {
+                for (i, kind) in ProviderKind::ALL.iter().enumerate() {
+                    let style = if i == *selected { selected_style } else { Style::default() };
+                    lines.push(Line::styled(format!(" {} ", kind.label()), style));
+                }
+                "↑/↓ 选择 · 回车确认 · ESC 取消"
+            }
+            Step::Endpoint { focus, error } => {
+                let field = |label: &str, value: String, focused: bool| {
+                    let style = if focused { Style::default().fg(Color::Cyan) } else { Style::default() };
+                    Line::from(vec![
+                        Span::styled(format!("{:<10}", label), style.add_modifier(Modifier::BOLD)),
+                        Span::styled(value, style),
+                        Span::styled(if focused { "▏" } else { "" }, style),
+                    ])
+                };
+                lines.push(field("服务地址", self.root.clone(), *focus == 0));
+                lines.push(field("API Key", "*".repeat(self.api_key.chars().count()), *focus == 1));
+                lines.push(Line::raw(""));
+                lines.push(Line::styled("本地服务通常无需 API Key，可留空", Style::default().fg(Color::DarkGray)));
+                if let Some(error) = error {
+                    lines.push(Line::raw(""));
+                    lines.push(Line::styled(error.clone(), Style::default().fg(Color::Red)));
+                }
+                "Tab 切换输入项 · 回车测试连接 · ESC 返回"
+            }
+            Step::Assign { role, selected } => {
+                lines.push(Line::styled(format!("{} 使用的模型:", ROLES[*role]), Style::default().add_modifier(Modifier::BOLD)));
+                // 列表较长时保持选中项可见
+                let visible = body_area.height.saturating_sub(3) as usize;
+                let start = (selected + 1).saturating_sub(visible.max(1));
+                for (i, model) in self.models.iter().enumerate().skip(start).take(visible) {
+                    let style = if i == *selected { selected_style } else { Style::default() };
+                    lines.push(Line::styled(format!(" {} ", model), style));
+                }
+                "↑/↓ 选择 · 回车确认 · ESC 返回"
+            }
+            Step::Review { error } => {
+                let config = self.config();
+                let provider = &config.providers[0];
+                lines.push(Line::raw(format!("服务类型   {}", provider.kind.label())));
+                lines.push(Line::raw(format!("对话接口   {}", provider.api_base)));
+                lines.push(Line::raw(format!("API Key    {}", if provider.api_key.is_empty() { "（无）" } else { "已填写" })));
+                lines.push(Line::raw(""));
+                for (role, model) in ROLES.iter().zip(&self.assigned) {
+                    lines.push(Line::raw(format!("{:<10} {}", role, model)));
+                }
+                if let Some(error) = error {
+                    lines.push(Line::raw(""));
+                    lines.push(Line::styled(error.clone(), Style::default().fg(Color::Red)));
+                }
+                "回车保存至 ~/.oxicodent/config.json · ESC 返回"
+            }
+        };
+
+        if let Some(busy) = busy {
+            lines.push(Line::raw(""));
+            lines.push(Line::styled(busy.to_string(), Style::default().fg(Color::Yellow)));
+        }
+
+        let body = Paragraph::new(lines)
+            .block(Block::default().borders(Borders::ALL))
+            .wrap(Wrap { trim: false });
+        f.render_widget(body, body_area);
+        f.render_widget(Paragraph::new(Span::styled(hint, Style::default().fg(Color::DarkGray))), hint_area);
+    }
+}
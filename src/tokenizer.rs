@@ -0,0 +1,180 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{Arc, RwLock};
+use base64::Engine;
+use tiktoken_rs::CoreBPE;
+use tracing::{info, warn};
+use crate::app::{ChatMessage, Model};
+use crate::config_manager::{get_config, TokenizerConfig};
+
+// 每条消息的角色标记等固定开销
+const MESSAGE_OVERHEAD: usize = 4;
+
+// 启发式估算的校准系数范围与更新权重
+const CALIBRATION_RANGE: (f64, f64) = (0.3, 3.0);
+const CALIBRATION_WEIGHT: f64 = 0.3;
+
+// tiktoken 文件不含预分词正则，按编码名选择
+const CL100K_PATTERN: &str = r"(?i:'s|'t|'re|'ve|'m|'ll|'d)|[^\r\n\p{L}\p{N}]?\p{L}+|\p{N}{1,3}| ?[^\s\p{L}\p{N}]+[\r\n]*|\s*[\r\n]+|\s+(?!\S)|\s+";
+const O200K_PATTERN: &str = concat!(
+    r"[^\r\n\p{L}\p{N}]?[\p{Lu}\p{Lt}\p{Lm}\p{Lo}\p{M}]*[\p{Ll}\p{Lm}\p{Lo}\p{M}]+(?i:'s|'t|'re|'ve|'m|'ll|'d)?",
+    r"|[^\r\n\p{L}\p{N}]?[\p{Lu}\p{Lt}\p{Lm}\p{Lo}\p{M}]+[\p{Ll}\p{Lm}\p{Lo}\p{M}]*(?i:'s|'t|'re|'ve|'m|'ll|'d)?",
+    r"|\p{N}{1,3}| ?[^\s\p{L}\p{N}]+[\r\n/]*|\s*[\r\n]+|\s+(?!\S)|\s+",
+);
+
+/*
+ * -------- [ token 估算 ] --------
+ * 无需分词器的启发式估算：
+ * - CJK 字符约 1 token / 字
+ * - 其余字符约 4 字符 / token
+ */
+pub fn estimate_tokens(text: &str) -> usize {
+    let (cjk, other) = text.chars().fold((0usize, 0usize), |(cjk, other), c| {
+        if is_cjk(c) { (cjk + 1, other) } else { (cjk, other + 1) }
+    });
+    cjk + other.div_ceil(4)
+}
+
+fn is_cjk(c: char) -> bool {
+    matches!(c as u32,
+        0x3040..=0x30FF      // 平假名、片假名
+        | 0x3400..=0x4DBF    // CJK 扩展 A
+        | 0x4E00..=0x9FFF    // CJK 统一汉字
+        | 0xAC00..=0xD7AF    // 韩文
+        | 0xF900..=0xFAFF    // CJK 兼容汉字
+        | 0xFF00..=0xFFEF    // 全角符号
+        | 0x3000..=0x303F)   // CJK 标点
+}
+
+/*
+ * -------- [ 本地分词器 ] --------
+ * config.tokenizers 按模型名指定分词器文件：
+ * - huggingface: tokenizer.json
+ * - tiktoken: BPE 文件（每行 base64 token 与 rank），pattern 为 cl100k / o200k 或自定义正则
+ * 未配置或加载失败时使用启发式估算，并按服务端上报的 prompt_tokens 校准
+ */
+enum Counter {
+    HuggingFace(Box<tokenizers::Tokenizer>),
+    Tiktoken(Box<CoreBPE>),
+    // 校准系数
+    Heuristic(f64),
+}
+
+impl Counter {
+    fn load(model_name: &str) -> Counter {
+        let config = get_config().read().unwrap().tokenizers.get(model_name).cloned();
+        let Some(config) = config else { return Counter::Heuristic(1.0) };
+
+        let loaded = match &config {
+            TokenizerConfig::Huggingface { path } => tokenizers::Tokenizer::from_file(expand_path(path))
+                .map(|t| Counter::HuggingFace(Box::new(t)))
+                .map_err(|e| e.to_string()),
+            TokenizerConfig::Tiktoken { path, pattern } => load_tiktoken(path, pattern.as_deref())
+                .map(|bpe| Counter::Tiktoken(Box::new(bpe))),
+        };
+        match loaded {
+            Ok(counter) => {
+                info!("模型 {} 使用分词器 {:?}", model_name, config);
+                counter
+            }
+            Err(e) => {
+                warn!("模型 {} 的分词器加载失败，改用估算: {}", model_name, e);
+                Counter::Heuristic(1.0)
+            }
+        }
+    }
+
+    fn count(&self, text: &str) -> usize {
+        match self {
+            Counter::HuggingFace(tokenizer) => tokenizer.encode(text, false)
+                .map(|encoding| encoding.len())
+                .unwrap_or_else(|_| estimate_tokens(text)),
+            Counter::Tiktoken(bpe) => bpe.encode_ordinary(text).len(),
+            Counter::Heuristic(factor) => (estimate_tokens(text) as f64 * factor).ceil() as usize,
+        }
+    }
+}
+
+fn load_tiktoken(path: &str, pattern: Option<&str>) -> Result<CoreBPE, String> {
+    let path = expand_path(path);
+    let content = fs::read_to_string(&path)
+        .map_err(|e| format!("无法读取 <{}>: {}", path.to_string_lossy(), e))?;
+
+    let mut encoder = HashMap::default();
+    for (i, line) in content.lines().enumerate().filter(|(_, l)| !l.trim().is_empty()) {
+        let parsed = line.split_once(' ').and_then(|(token, rank)| Some((
+            base64::engine::general_purpose::STANDARD.decode(token).ok()?,
+            rank.trim().parse().ok()?,
+        )));
+        let (token, rank) = parsed.ok_or(format!("<{}> 第 {} 行格式错误", path.to_string_lossy(), i + 1))?;
+        encoder.insert(token, rank);
+    }
+
+    // 未指定时按文件名猜测编码
+    let name = path.to_string_lossy();
+    let pattern = match pattern {
+        Some("cl100k") => CL100K_PATTERN,
+        Some("o200k") => O200K_PATTERN,
+        Some(custom) => custom,
+        None if name.contains("cl100k") => CL100K_PATTERN,
+        None => O200K_PATTERN,
+    };
+    CoreBPE::new(encoder, HashMap::default(), pattern).map_err(|e| e.to_string())
+}
+
+/// 支持 `~/` 开头的路径
+fn expand_path(path: &str) -> PathBuf {
+    match path.strip_prefix("~/") {
+        Some(rest) => std::env::home_dir().map_or(PathBuf::from(path), |home| home.join(rest)),
+        None => PathBuf::from(path),
+    }
+}
+
+// 按模型名缓存，首次使用时加载
+static COUNTERS: RwLock<Option<HashMap<String, Arc<RwLock<Counter>>>>> = RwLock::new(None);
+
+fn counter(model: &Model) -> Arc<RwLock<Counter>> {
+    let model_name = get_config().read().unwrap().model_name(model).to_string();
+    if let Some(counter) = COUNTERS.read().unwrap().as_ref().and_then(|c| c.get(&model_name)) {
+        return counter.clone()
+    }
+
+    let loaded = Arc::new(RwLock::new(Counter::load(&model_name)));
+    COUNTERS.write().unwrap()
+        .get_or_insert_with(HashMap::new)
+        .entry(model_name)
+        .or_insert(loaded)
+        .clone()
+}
+
+/// 配置重新载入后清空缓存
+pub fn reset() {
+    *COUNTERS.write().unwrap() = None;
+}
+
+/// 按角色所用模型的分词器计算 token 数
+pub fn count_tokens(model: &Model, text: &str) -> usize {
+    counter(model).read().unwrap().count(text)
+}
+
+/// 一组消息的 token 数
+pub fn count_messages(model: &Model, messages: &[ChatMessage]) -> usize {
+    let counter = counter(model);
+    let counter = counter.read().unwrap();
+    messages.iter().map(|m| counter.count(&m.content) + MESSAGE_OVERHEAD).sum()
+}
+
+/// 用服务端上报的 prompt_tokens 校准启发式估算；使用分词器时不作调整
+pub fn calibrate(model: &Model, counted: usize, reported: u64) {
+    if counted == 0 || reported == 0 {
+        return
+    }
+    let counter = counter(model);
+    let mut counter = counter.write().unwrap();
+    if let Counter::Heuristic(factor) = &mut *counter {
+        let observed = reported as f64 / (counted as f64 / *factor);
+        *factor = (*factor * (1.0 - CALIBRATION_WEIGHT) + observed * CALIBRATION_WEIGHT)
+            .clamp(CALIBRATION_RANGE.0, CALIBRATION_RANGE.1);
+    }
+}
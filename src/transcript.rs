@@ -0,0 +1,352 @@
+use chrono::{DateTime, Local};
+use ratatui::layout::Alignment;
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Paragraph, Wrap};
+use crate::app::{Model, ToolOutput};
+use crate::config_manager::get_config;
+use crate::highlight::highlight_numbered;
+use crate::markdown::render_markdown;
+
+/// 对话区中的一条记录
+pub enum EntryKind {
+    User(String),
+    Assistant { model: Model, content: String },
+    // 模型的思考过程，默认折叠
+    Reasoning(String),
+    // tool 为 exec / read / diff，detail 为命令或文件路径，result 在工具返回后填入
+    ToolCall { tool: &'static str, detail: String, result: Option<ToolOutput> },
+    Rejected(String),
+    Notice(String),
+    SystemError(String),
+}
+
+pub struct Entry {
+    pub kind: EntryKind,
+    pub timestamp: DateTime<Local>,
+    // 工具块 / 思考块是否展开
+    pub expanded: bool,
+    // 上一帧的渲染结果；内容变化时置空
+    rendered: Option<Rendered>,
+}
+
+/// 一条记录的渲染结果（末尾含一个空行）及其在对话区宽度下的折行高度
+pub struct Rendered {
+    key: RenderKey,
+    pub lines: Vec<Line<'static>>,
+    pub heights: Vec<usize>,
+}
+
+/// 影响渲染结果的参数，任一变化时重新渲染
+#[derive(PartialEq)]
+struct RenderKey {
+    width: u16,
+    expanded: bool,
+    selected: bool,
+    theme: String,
+}
+
+/*
+ * -------- [ 对话记录 ] --------
+ * UI 展示、滚动、复制与导出均基于该结构，不再从文本中猜测消息角色
+ */
+pub struct Transcript {
+    entries: Vec<Entry>,
+    // 当前选中的可折叠块（entries 下标）
+    selected: Option<usize>,
+}
+
+impl Transcript {
+    pub fn new() -> Self {
+        Self { entries: Vec::new(), selected: None }
+    }
+
+    pub fn push(&mut self, kind: EntryKind) {
+        self.entries.push(Entry { kind, timestamp: Local::now(), expanded: false, rendered: None });
+    }
+
+    /// 将工具结果填入最近一个同类、尚无结果的工具块；找不到时单独追加一块
+    pub fn push_result(&mut self, tool: &'static str, output: ToolOutput) {
+        let pending = self.entries.iter_mut().rev().find(|e| matches!(
+            &e.kind,
+            EntryKind::ToolCall { tool: t, result: None, .. } if *t == tool
+        ));
+        match pending {
+            Some(entry) => {
+                if let EntryKind::ToolCall { result, .. } = &mut entry.kind {
+                    *result = Some(output);
+                }
+                entry.rendered = None;
+            }
+            None => self.push(EntryKind::ToolCall { tool, detail: String::new(), result: Some(output) }),
+        }
+    }
+
+    /*
+     * -------- [ 渲染缓存 ] --------
+     * 按对话区宽度渲染全部记录；宽度、展开/选中状态与主题均未变化的记录复用上一帧的结果，
+     * 避免每帧重新解析 Markdown、高亮代码与计算折行高度
+     */
+    pub fn render(&mut self, width: u16) -> impl Iterator<Item = &Rendered> {
+        let theme = get_config().read().unwrap().theme.clone();
+        let selected = self.selected;
+        for (i, entry) in self.entries.iter_mut().enumerate() {
+            let key = RenderKey { width, expanded: entry.expanded, selected: selected == Some(i), theme: theme.clone() };
+            if entry.rendered.as_ref().is_some_and(|r| r.key == key) {
+                continue
+            }
+            let mut lines = entry.render(key.selected);
+            lines.push(Line::from(""));
+            let heights = lines.iter().map(|l| wrapped_height(l, width)).collect();
+            entry.rendered = Some(Rendered { key, lines, heights });
+        }
+        self.entries.iter().filter_map(|e| e.rendered.as_ref())
+    }
+
+    // --- [ 可折叠块选择（工具块、思考块） ] ---
+    pub fn selected(&self) -> Option<usize> {
+        self.selected
+    }
+
+    /// 选中上一个可折叠块；未选中时从最后一个开始
+    pub fn select_prev(&mut self) {
+        let end = self.selected.unwrap_or(self.entries.len());
+        if let Some(i) = self.entries[..end].iter().rposition(Entry::is_collapsible) {
+            self.selected = Some(i);
+        }
+    }
+
+    /// 选中下一个可折叠块；已是最后一个时取消选择
+    pub fn select_next(&mut self) {
+        let Some(current) = self.selected else { return };
+        self.selected = self.entries[current + 1..].iter()
+            .position(Entry::is_collapsible)
+            .map(|i| current + 1 + i);
+    }
+
+    pub fn clear_selection(&mut self) {
+        self.selected = None;
+    }
+
+    /// 展开/折叠选中的块；未选中时作用于最后一个可折叠块
+    pub fn toggle_selected(&mut self) {
+        let target = self.selected.or_else(|| self.entries.iter().rposition(Entry::is_collapsible));
+        if let Some(entry) = target.and_then(|i| self.entries.get_mut(i)) {
+            entry.expanded = !entry.expanded;
+        }
+    }
+
+    /// 倒数第 n 条（从 1 开始）模型回复
+    pub fn nth_last_reply(&self, n: usize) -> Option<&str> {
+        self.entries.iter().rev()
+            .filter_map(|e| match &e.kind {
+                EntryKind::Assistant { content, .. } => Some(content.as_str()),
+                _ => None
+            })
+            .nth(n.checked_sub(1)?)
+    }
+
+    /// 导出为 Markdown 文本
+    pub fn to_markdown(&self) -> String {
+        let mut output = String::from("# Oxicodent Transcript\n");
+        for entry in &self.entries {
+            let time = entry.timestamp.format("%Y-%m-%d %H:%M:%S");
+            let section = match &entry.kind {
+                EntryKind::User(content) => format!("## USER · {}\n\n{}", time, content),
+                EntryKind::Assistant { model, content } => format!("## {} · {}\n\n{}", model.name(), time, content),
+                EntryKind::Reasoning(content) =>
+                    format!("<details>\n<summary>思考过程 · {}</summary>\n\n{}\n\n</details>", time, content.trim()),
+                EntryKind::ToolCall { tool, detail, result } => {
+                    let mut section = format!("> [{}] 调用 {} `{}`", time, tool, detail.trim());
+                    if let Some(result) = result {
+                        section.push_str(&format!(" · {}", result_summary(result)));
+                        section.push_str(&format!("\n\n```{}-result\n{}\n```", tool, result.content.trim_end()));
+                    }
+                    section
+                }
+                EntryKind::Rejected(msg) => format!("> [{}] REJECTED: {}", time, msg),
+                EntryKind::Notice(msg) => format!("> [{}] INFO: {}", time, msg),
+                EntryKind::SystemError(msg) => format!("> [{}] ERROR: {}", time, msg),
+            };
+            output.push('\n');
+            output.push_str(&section);
+            output.push('\n');
+        }
+        output
+    }
+}
+
+impl Entry {
+    fn is_collapsible(&self) -> bool {
+        matches!(self.kind, EntryKind::ToolCall { .. } | EntryKind::Reasoning(_))
+    }
+
+    /// selected 为该条目是否处于键盘选中状态
+    fn render(&self, selected: bool) -> Vec<Line<'static>> {
+        let time = self.timestamp.format("%H:%M:%S").to_string();
+        let dim = Style::default().fg(Color::DarkGray);
+
+        match &self.kind {
+            // 用户的话：右对齐，绿色
+            EntryKind::User(content) => {
+                let style = Style::default().fg(Color::Green);
+                let mut lines = vec![
+                    Line::from(vec![
+                        Span::styled(format!("{} ", time), dim),
+                        Span::styled("USER", style.add_modifier(Modifier::BOLD)),
+                    ]).alignment(Alignment::Right)
+                ];
+                lines.extend(content.lines().map(|l| Line::styled(l.to_string(), style).alignment(Alignment::Right)));
+                lines
+            }
+            // 模型的话：标签行 + Markdown 正文
+            EntryKind::Assistant { model, content } => {
+                let mut lines = vec![assistant_header(model, Some(&time))];
+                lines.extend(render_markdown(content, assistant_style()));
+                lines
+            }
+            EntryKind::Reasoning(content) => render_reasoning(Some(&time), content, self.expanded, selected),
+            EntryKind::ToolCall { tool, detail, result } =>
+                render_tool_block(&time, tool, detail, result.as_ref(), self.expanded, selected),
+            EntryKind::Rejected(msg) => vec![status_line(&time, "REJECTED", Color::Magenta, msg)],
+            EntryKind::Notice(msg) => {
+                let mut lines = msg.lines();
+                let mut output = vec![status_line(&time, "INFO", Color::Blue, lines.next().unwrap_or(""))];
+                output.extend(lines.map(|l| Line::raw(l.to_string())));
+                output
+            }
+            EntryKind::SystemError(msg) => {
+                let mut lines = msg.lines();
+                let mut output = vec![status_line(&time, "ERROR", Color::Red, lines.next().unwrap_or(""))];
+                output.extend(lines.map(|l| Line::styled(l.to_string(), Style::default().fg(Color::Red))));
+                output
+            }
+        }
+    }
+}
+
+/// 逻辑行在给定宽度下折行后的高度
+pub fn wrapped_height(line: &Line, width: u16) -> usize {
+    if line.width() <= width as usize {
+        return 1
+    }
+    Paragraph::new(line.clone()).wrap(Wrap { trim: false }).line_count(width).max(1)
+}
+
+pub fn assistant_style() -> Style {
+    Style::default().fg(Color::Cyan)
+}
+
+/// 模型回复的标签行；流式输出中尚无时间戳
+pub fn assistant_header(model: &Model, time: Option<&str>) -> Line<'static> {
+    let mut spans = vec![Span::styled(model.name(), assistant_style().add_modifier(Modifier::BOLD))];
+    if let Some(time) = time {
+        spans.push(Span::styled(format!(" {}", time), Style::default().fg(Color::DarkGray)));
+    }
+    Line::from(spans)
+}
+
+/*
+ * -------- [ 思考块 ] --------
+ * 暗色显示；折叠时仅显示一行摘要，生成中（无时间戳）始终展开
+ */
+pub fn render_reasoning(time: Option<&str>, content: &str, expanded: bool, selected: bool) -> Vec<Line<'static>> {
+    let dim = Style::default().fg(Color::DarkGray);
+    let mut header_style = dim.add_modifier(Modifier::BOLD);
+    if selected {
+        header_style = header_style.add_modifier(Modifier::REVERSED);
+    }
+
+    let content = content.trim();
+    let marker = if expanded { "▾" } else { "▸" };
+    let mut header = Vec::new();
+    if let Some(time) = time {
+        header.push(Span::styled(format!("{} ", time), dim));
+    }
+    header.push(Span::styled(format!("{} ✻ 思考过程 ", marker), header_style));
+    header.push(Span::styled(format!("· {} 行", content.lines().count()), dim));
+
+    let mut lines = vec![Line::from(header)];
+    if expanded {
+        let style = dim.add_modifier(Modifier::ITALIC);
+        lines.extend(content.lines().map(|l| Line::from(vec![Span::styled("  │ ", dim), Span::styled(l.to_string(), style)])));
+    }
+    lines
+}
+
+/*
+ * -------- [ 工具块 ] --------
+ * 折叠时仅显示一行摘要（命令、退出状态、耗时、行数），展开后显示完整命令与结果
+ */
+fn render_tool_block(time: &str, tool: &str, detail: &str, result: Option<&ToolOutput>, expanded: bool, selected: bool) -> Vec<Line<'static>> {
+    let dim = Style::default().fg(Color::DarkGray);
+    let mut header_style = Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD);
+    if selected {
+        header_style = header_style.add_modifier(Modifier::REVERSED);
+    }
+
+    let mut summary = vec![Span::styled(detail.lines().next().unwrap_or("").trim().to_string(), Style::default())];
+    if detail.trim().lines().count() > 1 {
+        summary.push(Span::styled(" …", dim));
+    }
+    match result {
+        None => summary.push(Span::styled(" · 执行中", dim)),
+        Some(result) => {
+            let color = if result.success { Color::Green } else { Color::Red };
+            summary.push(Span::styled(format!(" · {}", result.status), Style::default().fg(color)));
+            summary.push(Span::styled(format!(" · {}", result_details(result)), dim));
+        }
+    }
+
+    let marker = if expanded { "▾" } else { "▸" };
+    let mut lines = vec![Line::from([
+        vec![
+            Span::styled(format!("{} ", time), dim),
+            Span::styled(format!("{} ⚙ {} ", marker, tool), header_style),
+        ],
+        summary,
+    ].concat())];
+
+    if !expanded {
+        return lines
+    }
+
+    let gutter = || Span::styled("  │ ", dim);
+    if detail.trim().lines().count() > 1 {
+        lines.extend(detail.trim().lines().map(|l| Line::from(vec![gutter(), Span::raw(l.to_string())])));
+        lines.push(Line::from(vec![gutter()]));
+    }
+    if let Some(result) = result {
+        if tool == "read" && result.success {
+            lines.extend(highlight_numbered(detail.trim(), &result.content).into_iter().map(|spans| {
+                let mut line = vec![gutter()];
+                line.extend(spans);
+                Line::from(line)
+            }));
+        } else {
+            lines.extend(result.content.lines().map(|l| Line::from(vec![gutter(), Span::styled(l.to_string(), dim)])));
+        }
+    }
+    lines
+}
+
+/// 耗时、行数与截断情况，如 `1.2s · 400 行（截断自 2000 行）`
+fn result_details(result: &ToolOutput) -> String {
+    let mut details = format!("{:.1}s · {} 行", result.elapsed.as_secs_f64(), result.content.lines().count());
+    if let Some(total) = result.truncated {
+        details.push_str(&format!("（截断自 {} 行）", total));
+    }
+    details
+}
+
+fn result_summary(result: &ToolOutput) -> String {
+    format!("{} · {}", result.status, result_details(result))
+}
+
+fn status_line(time: &str, label: &str, color: Color, msg: &str) -> Line<'static> {
+    Line::from(vec![
+        Span::styled(format!("{} ", time), Style::default().fg(Color::DarkGray)),
+        Span::styled(format!("[{}] ", label), Style::default().fg(color).add_modifier(Modifier::BOLD)),
+        Span::raw(msg.to_string()),
+    ])
+}
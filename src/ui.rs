@@ -1,70 +1,411 @@
 use crate::{PendingAction, AppTerminal, get_logo_text};
+use crate::input_editor::InputEditor;
+use crate::markdown::render_markdown;
+use crate::transcript::{assistant_header, assistant_style, render_reasoning, wrapped_height, EntryKind, Transcript};
+use crate::app::{get_model, Model, ToolOutput};
+use crate::config_manager::get_config;
+use crate::tokenizer::estimate_tokens;
+use crate::usage::UsageTotals;
+use crate::redact::redact;
+use std::io::Write;
+use std::time::Instant;
+use base64::Engine;
 
-use ratatui::{text::{Line, Span}, layout::{Constraint, Direction, Layout, Alignment}, widgets::{Block, Borders, Paragraph, Wrap}, style::{Style, Color}, Terminal};
+use ratatui::{text::{Line, Span}, layout::{Constraint, Direction, Layout, Alignment}, widgets::{Block, Borders, Paragraph, Wrap}, style::{Style, Color, Modifier}, Terminal};
 use ratatui::backend::CrosstermBackend;
 
+const INPUT_MAX_LINES: usize = 10;
+const SPINNER: [&str; 10] = ["⠋", "⠙", "⠹", "⠸", "⠼", "⠴", "⠦", "⠧", "⠇", "⠏"];
+
 pub struct Ui {
     terminal: AppTerminal,
-    pub input: String,
-    pub history_display: String,
+    // 按 Model::ALL 顺序，当前标签页即 get_model() 对应的角色
+    tabs: Vec<Tab>,
+    pub context_tokens: [usize; 4],
+    pub usage: UsageTotals,
+    // 待写入终端剪贴板的内容（OSC 52，已 base64 编码），在下一帧绘制后经终端后端输出
+    clipboard: Option<String>,
+}
+
+/*
+ * -------- [ 角色标签页 ] --------
+ * 每个角色拥有独立的对话记录、输入框、滚动位置与待确认操作，
+ * 后台标签页的流式输出与工具调用照常进行
+ */
+pub struct Tab {
+    pub model: Model,
+    pub input: InputEditor,
+    pub transcript: Transcript,
     pub current_ai_response: String,
+    // 正在生成的思考过程
+    pub current_reasoning: String,
     pub pending_action: PendingAction,
-    pub scroll_offset: u16,
-    pub is_auto_scroll: bool,
+    pub scroll: ScrollState,
+    pub stream: Option<StreamStats>,
+}
+
+impl Tab {
+    fn new(model: Model, input: InputEditor) -> Self {
+        Self {
+            model,
+            input,
+            transcript: Transcript::new(),
+            current_ai_response: String::new(),
+            current_reasoning: String::new(),
+            pending_action: PendingAction::None,
+            scroll: ScrollState::new(),
+            stream: None,
+        }
+    }
+
+    /// 追加一条对话记录
+    pub fn push(&mut self, kind: EntryKind) {
+        self.transcript.push(kind);
+        self.scroll.mark_new_output();
+    }
+
+    /// 工具返回结果，填入对应的工具块
+    pub fn push_result(&mut self, tool: &'static str, output: ToolOutput) {
+        self.transcript.push_result(tool, output);
+        self.scroll.mark_new_output();
+    }
+
+    // --- [ 工具块 / 思考块选择 ] ---
+    pub fn select_prev_block(&mut self) {
+        self.transcript.select_prev();
+        self.scroll.reveal_selected();
+    }
+
+    pub fn select_next_block(&mut self) {
+        self.transcript.select_next();
+        self.scroll.reveal_selected();
+    }
+
+    pub fn toggle_block(&mut self) {
+        self.transcript.toggle_selected();
+        self.scroll.reveal_selected();
+    }
+
+    pub fn start_stream(&mut self) {
+        self.stream = Some(StreamStats { started: Instant::now(), first_chunk: None, tokens: 0 });
+    }
+
+    pub fn stream_chunk(&mut self, chunk: &str) {
+        if let Some(stream) = &mut self.stream {
+            stream.first_chunk.get_or_insert_with(Instant::now);
+            stream.tokens += estimate_tokens(chunk);
+        }
+    }
+}
+
+/// 正在进行的流式请求
+pub struct StreamStats {
+    started: Instant,
+    first_chunk: Option<Instant>,
+    tokens: usize,
+}
+
+impl StreamStats {
+    /// 已生成内容的输出速度（tokens/s，按首个片段到达后计时）
+    fn tokens_per_sec(&self) -> Option<f64> {
+        let elapsed = self.first_chunk?.elapsed().as_secs_f64();
+        (elapsed > 0.0).then(|| self.tokens as f64 / elapsed)
+    }
+}
+
+/*
+ * -------- [ 对话区滚动状态 ] --------
+ * - offset 以折行后的显示行计（usize，长会话不会溢出）
+ * - line_heights 为上一帧每个逻辑行折行后的高度，用于窗口宽度变化时保持阅读位置
+ * - follow 为自动滚动：有新输出时始终停在底部；向上滚动时关闭，回到底部时恢复
+ */
+pub struct ScrollState {
+    offset: usize,
+    follow: bool,
+    has_new_output: bool,
+    viewport: usize,
+    width: u16,
+    line_heights: Vec<usize>,
+    // 选中的工具块发生变化，下一帧需要滚动到可见位置
+    reveal: bool,
+}
+
+impl ScrollState {
+    fn new() -> Self {
+        Self { offset: 0, follow: true, has_new_output: false, viewport: 0, width: 0, line_heights: Vec::new(), reveal: false }
+    }
+
+    fn max_offset(&self) -> usize {
+        self.line_heights.iter().sum::<usize>().saturating_sub(self.viewport)
+    }
+
+    pub fn scroll_up(&mut self, rows: usize) {
+        self.offset = self.offset.saturating_sub(rows);
+        self.follow = false;
+    }
+
+    pub fn scroll_down(&mut self, rows: usize) {
+        self.offset = self.offset.saturating_add(rows);
+        if self.offset >= self.max_offset() {
+            self.scroll_to_bottom();
+        }
+    }
+
+    pub fn page_up(&mut self) {
+        self.scroll_up(self.viewport.saturating_sub(1).max(1));
+    }
+
+    pub fn page_down(&mut self) {
+        self.scroll_down(self.viewport.saturating_sub(1).max(1));
+    }
+
+    pub fn scroll_to_top(&mut self) {
+        self.offset = 0;
+        self.follow = false;
+    }
+
+    pub fn scroll_to_bottom(&mut self) {
+        self.offset = self.max_offset();
+        self.follow = true;
+        self.has_new_output = false;
+    }
+
+    /// 下一帧将选中的工具块滚动到可见位置
+    pub fn reveal_selected(&mut self) {
+        self.reveal = true;
+    }
+
+    /// 有新内容输出；未处于自动滚动时显示提示
+    pub fn mark_new_output(&mut self) {
+        if !self.follow {
+            self.has_new_output = true;
+        }
+    }
+
+    /*
+     * 根据本帧的行高与视口更新状态，返回 (起始逻辑行, 该行内跳过的显示行)
+     * 宽度变化时，先按旧行高换算出当前首行，再按新行高恢复偏移
+     * selected 为选中工具块的首个逻辑行，需要时将其滚动到可见位置
+     */
+    fn layout(&mut self, line_heights: Vec<usize>, width: u16, viewport: usize, selected: Option<usize>) -> (usize, usize) {
+        if width != self.width && !self.follow {
+            let (line, _) = locate(&self.line_heights, self.offset);
+            self.offset = line_heights.iter().take(line).sum();
+        }
+        self.width = width;
+        self.viewport = viewport;
+        self.line_heights = line_heights;
+
+        if std::mem::take(&mut self.reveal) && let Some(line) = selected {
+            let top: usize = self.line_heights.iter().take(line).sum();
+            if top < self.offset || top >= self.offset + viewport {
+                self.offset = top;
+                self.follow = false;
+            }
+        }
+
+        let max_offset = self.max_offset();
+        if self.follow || self.offset >= max_offset {
+            self.offset = max_offset;
+            self.follow = true;
+            self.has_new_output = false;
+        }
+
+        locate(&self.line_heights, self.offset)
+    }
+}
+
+/// 将显示行偏移换算为 (逻辑行下标, 行内偏移)
+fn locate(line_heights: &[usize], offset: usize) -> (usize, usize) {
+    let mut remaining = offset;
+    for (i, height) in line_heights.iter().enumerate() {
+        if remaining < *height {
+            return (i, remaining)
+        }
+        remaining -= height;
+    }
+    (line_heights.len(), 0)
 }
 
 impl Ui {
     pub fn new() -> Self {
+        // 各标签页的输入框共享同一份输入历史
+        let input = InputEditor::new();
+        let tabs = Model::ALL.map(|model| Tab::new(model, input.share_history())).into();
         Self {
             terminal: Terminal::new(CrosstermBackend::new(std::io::stdout())).unwrap(),
-            input: String::new(),
-            history_display: String::new(),
-            current_ai_response: String::new(),
-            pending_action: PendingAction::None,
-            scroll_offset: 0,
-            is_auto_scroll: true,
+            tabs,
+            context_tokens: [0; 4],
+            usage: UsageTotals::default(),
+            clipboard: None,
         }
     }
 
-    pub fn auto_scroll(&mut self) {
-        let terminal_height = self.terminal.size().unwrap().height;
-        // 粗略估算对话框高度（总高度 - 输入框3行 - 边框2行）
-        let chat_height = terminal_height.saturating_sub(5);
+    /// 复制到终端剪贴板；终端由 ratatui 接管，转义序列留到绘制后统一写入
+    pub fn copy_to_clipboard(&mut self, text: &str) {
+        self.clipboard = Some(base64::engine::general_purpose::STANDARD.encode(text));
+    }
+
+    /// 当前标签页
+    pub fn tab(&self) -> &Tab {
+        &self.tabs[get_model().read().unwrap().index()]
+    }
+
+    pub fn tab_mut(&mut self) -> &mut Tab {
+        self.tab_of(*get_model().read().unwrap())
+    }
+
+    /// 指定角色的标签页
+    pub fn tab_of(&mut self, model: Model) -> &mut Tab {
+        &mut self.tabs[model.index()]
+    }
+
+    /// 切换标签页，同时切换当前模型
+    pub fn switch_tab(&mut self, model: Model) {
+        *get_model().write().unwrap() = model;
+    }
+
+    /*
+     * -------- [ 状态栏 ] --------
+     * 角色与模型 | 流式状态 | 上下文占用 | 待确认操作
+     */
+    fn status_line(&self) -> Line<'static> {
+        let tab = self.tab();
+        let model = tab.model;
+        let config = get_config().read().unwrap();
+        let sep = Span::styled(" │ ", Style::default().fg(Color::DarkGray));
+
+        let api_base = &config.provider(&model).api_base;
+        let endpoint = api_base.split("://").nth(1).unwrap_or(api_base)
+            .split('/').next().unwrap_or("").to_string();
+        let mut spans = vec![
+            Span::styled(format!(" {} ", model.name()), Style::default().fg(Color::Black).bg(Color::Cyan).add_modifier(Modifier::BOLD)),
+            Span::raw(format!(" {} @ {}", config.model_name(&model), endpoint)),
+            sep.clone(),
+        ];
+
+        match &tab.stream {
+            Some(stream) => {
+                let elapsed = stream.started.elapsed();
+                let frame = SPINNER[(elapsed.as_millis() / 100) as usize % SPINNER.len()];
+                let mut text = format!("{} 生成中 {:.1}s", frame, elapsed.as_secs_f64());
+                if let Some(rate) = stream.tokens_per_sec() {
+                    text.push_str(&format!(" · {:.0} tok/s", rate));
+                }
+                spans.push(Span::styled(text, Style::default().fg(Color::Yellow)));
+            }
+            None => spans.push(Span::styled("就绪", Style::default().fg(Color::Green))),
+        }
+        spans.push(sep.clone());
+
+        let used = self.context_tokens[model.index()];
+        let window = config.context_window(&model).max(1);
+        let percent = used * 100 / window;
+        let color = match percent {
+            0..=69 => Color::Green,
+            70..=89 => Color::Yellow,
+            _ => Color::Red,
+        };
+        spans.push(Span::styled(
+            format!("上下文 {}/{} ({}%)", format_tokens(used), format_tokens(window), percent),
+            Style::default().fg(color),
+        ));
+        spans.push(sep.clone());
+
+        // 会话累计用量
+        if self.usage.requests > 0 {
+            let mut text = format!("↑{} ↓{}", format_tokens(self.usage.prompt_tokens as usize), format_tokens(self.usage.completion_tokens as usize));
+            if let Some(cost) = self.usage.cost {
+                text.push_str(&format!(" ${:.2}", cost));
+            }
+            spans.push(Span::styled(text, Style::default().fg(Color::DarkGray)));
+            spans.push(sep);
+        }
 
-        // 计算当前显示的所有行数（包括 Logo 和 历史记录）
-        let logo_lines = 14;
-        let history_lines = self.history_display.lines().count() as u16;
-        let current_ai_lines = self.current_ai_response.lines().count() as u16;
+        let pending = match &tab.pending_action {
+            PendingAction::None => Span::styled("无待确认操作", Style::default().fg(Color::DarkGray)),
+            PendingAction::ConfirmExec(_) => Span::styled("待确认: exec", Style::default().fg(Color::Red)),
+            PendingAction::ConfirmDiff(file_path, _) => Span::styled(format!("待确认: diff {}", file_path), Style::default().fg(Color::Red)),
+            PendingAction::RejectReason(..) => Span::styled("填写拒绝理由", Style::default().fg(Color::Magenta)),
+        };
+        spans.push(pending);
+
+        Line::from(spans)
+    }
 
-        let total_lines = logo_lines + history_lines + current_ai_lines;
+    /*
+     * -------- [ 标签栏 ] --------
+     * 生成中的标签页显示 ⟳，有待确认操作的显示 !
+     */
+    fn tab_bar(&self) -> Line<'static> {
+        let active = self.tab().model;
+        let mut spans = Vec::new();
+        for (i, tab) in self.tabs.iter().enumerate() {
+            let mut label = format!(" F{} {}", i + 1, tab.model.name());
+            if tab.stream.is_some() {
+                label.push_str(" ⟳");
+            }
+            if !matches!(tab.pending_action, PendingAction::None) {
+                label.push_str(" !");
+            }
+            label.push(' ');
 
-        if total_lines > chat_height {
-            self.scroll_offset = total_lines - chat_height;
-        } else {
-            self.scroll_offset = 0;
+            let style = if tab.model == active {
+                Style::default().fg(Color::Black).bg(Color::Cyan).add_modifier(Modifier::BOLD)
+            } else if matches!(tab.pending_action, PendingAction::None) {
+                Style::default().fg(Color::DarkGray)
+            } else {
+                Style::default().fg(Color::Red)
+            };
+            spans.push(Span::styled(label, style));
+            spans.push(Span::raw(" "));
         }
+        Line::from(spans)
     }
 
+    /// 在当前标签页追加一条对话记录
+    pub fn push(&mut self, kind: EntryKind) {
+        self.tab_mut().push(kind);
+    }
+
+    /// 在对话区输出提示信息
+    pub fn push_notice(&mut self, msg: &str) {
+        self.push(EntryKind::Notice(redact(msg)));
+    }
+
+    /// 在对话区输出错误信息
+    pub fn push_error(&mut self, msg: &str) {
+        self.push(EntryKind::SystemError(redact(msg)));
+    }
 
     pub fn render(&mut self) {
+        let status = self.status_line();
+        let tab_bar = self.tab_bar();
+        let tab = &mut self.tabs[get_model().read().unwrap().index()];
+
         // --- UI 渲染循环 ---
         self.terminal.draw(|f| {
-            // 对话区(自动拉伸) | 输入框(固定高度)
-            let chunks = Layout::default()
+            // 标签栏 | 对话区(自动拉伸) | 输入框(随内容增高，最多 INPUT_MAX_LINES 行) | 状态栏
+            let input_lines = tab.input.line_count().min(INPUT_MAX_LINES) as u16;
+            let [tab_area, chat_area, input_area, status_area] = Layout::default()
                 .direction(Direction::Vertical)
                 .constraints([
+                    Constraint::Length(1),
                     Constraint::Min(10),
-                    Constraint::Length(3),
+                    Constraint::Length(input_lines + 2),
+                    Constraint::Length(1),
                 ])
-                .split(f.area());
+                .areas(f.area());
 
             // --- 构建带样式的对话流 ---
-            let mut lines = Vec::new();
+            let inner_width = chat_area.width.saturating_sub(2);
+            let viewport = chat_area.height.saturating_sub(2) as usize;
 
             // A. 渲染红色居中的 Logo
+            let mut head = Vec::new();
             let logo_str = get_logo_text();
             for line in logo_str.lines() {
-                lines.push(
+                head.push(
                     Line::from(Span::styled(
                         line,
                         Style::default().fg(Color::Red),
@@ -72,81 +413,112 @@ impl Ui {
                         .alignment(Alignment::Center),
                 );
             }
-            lines.push(Line::from("")); // 留白行
-
-            // B. 渲染历史记录（左右对齐）
-            for hist_line in self.history_display.lines() {
-                if hist_line.starts_with("USER:") {
-                    // 用户的话：右对齐，绿色
-                    lines.push(
-                        Line::from(Span::styled(
-                            hist_line,
-                            Style::default().fg(Color::Green),
-                        ))
-                            .alignment(Alignment::Right),
-                    );
-                } else if hist_line.starts_with("ASSISTANT:") {
-                    // 模型的话：左对齐，青色
-                    lines.push(
-                        Line::from(Span::styled(
-                            hist_line,
-                            Style::default().fg(Color::Cyan),
-                        ))
-                            .alignment(Alignment::Left),
-                    );
-                } else {
-                    // 这里的 line 可能是执行结果或者换行，默认左对齐
-                    lines.push(Line::from(hist_line).alignment(Alignment::Left));
-                }
+            head.push(Line::from("")); // 留白行
+
+            // C. 渲染正在生成的 AI 回复（思考过程在前）；内容每帧变化，不缓存
+            let mut tail = Vec::new();
+            if !tab.current_reasoning.is_empty() || !tab.current_ai_response.is_empty() {
+                tail.push(assistant_header(&tab.model, None));
+            }
+            if !tab.current_reasoning.is_empty() {
+                tail.extend(render_reasoning(None, &tab.current_reasoning, true, false));
+            }
+            if !tab.current_ai_response.is_empty() {
+                tail.extend(render_markdown(&tab.current_ai_response, assistant_style()));
             }
 
-            // C. 渲染正在生成的 AI 回复
-            if !self.current_ai_response.is_empty() {
-                // 先添加 ASSISTANT: 标签行
-                lines.push(
-                    Line::from(Span::styled(
-                        "\nASSISTANT:",
-                        Style::default().fg(Color::Cyan),
-                    ))
-                        .alignment(Alignment::Left),
-                );
-                // 将响应按行分割，每行都应用 Cyan 样式
-                for line in self.current_ai_response.lines() {
-                    lines.push(
-                        Line::from(Span::styled(
-                            line,
-                            Style::default().fg(Color::Cyan),
-                        ))
-                            .alignment(Alignment::Left),
-                    );
+            // B. 渲染对话记录（复用未变化记录的渲染结果）
+            let selected = tab.transcript.selected();
+            let rendered: Vec<_> = tab.transcript.render(inner_width).collect();
+            let mut line_heights: Vec<usize> = head.iter().map(|l| wrapped_height(l, inner_width)).collect();
+            let mut selected_line = None;
+            for (i, entry) in rendered.iter().enumerate() {
+                if selected == Some(i) {
+                    selected_line = Some(line_heights.len());
                 }
+                line_heights.extend(&entry.heights);
             }
+            line_heights.extend(tail.iter().map(|l| wrapped_height(l, inner_width)));
 
             /*
              * -------- [ TUI 渲染 ] --------
              */
+            // --- 0. 渲染标签栏 ---
+            f.render_widget(Paragraph::new(tab_bar), tab_area);
+
             // --- 1. 渲染对话框 ---
+            // 按真实折行高度定位可见区域，只把可见的逻辑行交给 Paragraph
+            let (start, skip) = tab.scroll.layout(line_heights, inner_width, viewport, selected_line);
+            let mut remaining = skip + viewport;
+            let lines: Vec<Line> = head.iter()
+                .chain(rendered.iter().flat_map(|entry| entry.lines.iter()))
+                .chain(tail.iter())
+                .zip(tab.scroll.line_heights.iter())
+                .skip(start)
+                .take_while(|(_, height)| {
+                    let visible = remaining > 0;
+                    remaining = remaining.saturating_sub(**height);
+                    visible
+                })
+                .map(|(line, _)| line.clone())
+                .collect();
+
             let chat_block = Paragraph::new(lines)
-                .block(Block::default().borders(Borders::ALL).title(" Oxicodent Chat "))
+                .block(Block::default().borders(Borders::ALL).title(format!(" Oxicodent Chat · {} ", tab.model.name())))
                 .wrap(Wrap { trim: false })
-                .scroll((self.scroll_offset, 0));
-            f.render_widget(chat_block, chunks[0]);
+                .scroll((skip as u16, 0));
+            f.render_widget(chat_block, chat_area);
+
+            // 未自动滚动时，提示下方有新输出
+            if tab.scroll.has_new_output && chat_area.height > 2 {
+                let hint = Line::from(Span::styled(
+                    " ↓ 下方有新输出 (End 跳至底部) ",
+                    Style::default().fg(Color::Black).bg(Color::Yellow),
+                )).alignment(Alignment::Right);
+                let hint_area = ratatui::layout::Rect {
+                    x: chat_area.x + 1,
+                    y: chat_area.y + chat_area.height - 2,
+                    width: inner_width,
+                    height: 1,
+                };
+                f.render_widget(Paragraph::new(hint), hint_area);
+            }
 
             // --- 2. 渲染输入框 ---
-            let input_block = Paragraph::new(self.input.as_str())
-                .block(Block::default().borders(Borders::ALL).title(" 输入 (回车发送, ESC退出) "));
-            f.render_widget(input_block, chunks[1]);
+            // 光标超出可视区域时滚动输入框
+            let (row, col) = tab.input.cursor_position();
+            let inner_width = input_area.width.saturating_sub(2) as usize;
+            let row_offset = (row + 1).saturating_sub(INPUT_MAX_LINES);
+            let col_offset = (col + 1).saturating_sub(inner_width);
+            let input_block = Paragraph::new(tab.input.text())
+                .block(Block::default().borders(Borders::ALL).title(" 输入 (回车发送, Shift/Alt+回车换行, F1-F4 切换角色, ESC退出) "))
+                .scroll((row_offset as u16, col_offset as u16));
+            f.render_widget(input_block, input_area);
 
-            // --- 3. 渲染弹窗 (覆盖在最上方) ---
+            if let PendingAction::None = &tab.pending_action {
+                f.set_cursor_position((
+                    input_area.x + 1 + (col - col_offset) as u16,
+                    input_area.y + 1 + (row - row_offset) as u16,
+                ));
+            }
+
+            // --- 3. 渲染状态栏 ---
+            f.render_widget(Paragraph::new(status), status_area);
+
+            // --- 4. 渲染弹窗 (覆盖在最上方) ---
             let area = centered_rect(60, 20, f.area());
+            let title = match &tab.pending_action {
+                PendingAction::RejectReason(..) => " 拒绝理由 ",
+                _ => " 确认执行？ "
+            };
             let block = Block::default()
-                .title(" 确认执行？ ")
+                .title(title)
                 .borders(Borders::ALL)
-                .border_style(Style::default().fg(Color::Red).add_modifier(ratatui::style::Modifier::BOLD));
-            match &self.pending_action {
+                .border_style(Style::default().fg(Color::Red).add_modifier(Modifier::BOLD));
+            match &tab.pending_action {
                 PendingAction::ConfirmExec(cmd) => {
                     f.render_widget(ratatui::widgets::Clear, area);
-                    let text = Paragraph::new(format!("\n待执行:\n{}\n\n按 [Y] 确认 / [N] 取消", cmd))
+                    let text = Paragraph::new(format!("\n待执行:\n{}\n\n按 [Y] 确认 / [N] 拒绝", cmd))
                         .block(block)
                         .alignment(Alignment::Center)
                         .wrap(Wrap { trim: true });
@@ -155,7 +527,16 @@ impl Ui {
 
                 PendingAction::ConfirmDiff(file_path, diff) => {
                     f.render_widget(ratatui::widgets::Clear, area);
-                    let text = Paragraph::new(format!("\n待应用补丁至 <{}>:\n{}\n\n按 [Y] 确认 / [N] 取消",file_path, diff))
+                    let text = Paragraph::new(format!("\n待应用补丁至 <{}>:\n{}\n\n按 [Y] 确认 / [N] 拒绝",file_path, diff))
+                        .block(block)
+                        .alignment(Alignment::Center)
+                        .wrap(Wrap { trim: true });
+                    f.render_widget(text, area);
+                }
+
+                PendingAction::RejectReason(_, reason) => {
+                    f.render_widget(ratatui::widgets::Clear, area);
+                    let text = Paragraph::new(format!("\n已拒绝: {}\n\n理由 (可留空): {}\n\n按 [回车] 发送 / [ESC] 返回", tab.pending_action.describe(), reason))
                         .block(block)
                         .alignment(Alignment::Center)
                         .wrap(Wrap { trim: true });
@@ -164,6 +545,13 @@ impl Ui {
                 _ => {}
             }
         }).unwrap();
+
+        if let Some(encoded) = self.clipboard.take() {
+            let backend = self.terminal.backend_mut();
+            if let Err(e) = write!(backend, "\x1b]52;c;{}\x07", encoded).and_then(|_| backend.flush()) {
+                self.push_error(&format!("无法写入剪贴板: {}", e));
+            }
+        }
     }
 }
 
@@ -183,3 +571,12 @@ fn centered_rect(percent_x: u16, percent_y: u16, r: ratatui::layout::Rect) -> ra
     let popup_layout = split(Direction::Vertical, percent_y, r);
     split(Direction::Horizontal, percent_x, popup_layout[1])[1]
 }
+
+/// 12345 -> 12.3k
+fn format_tokens(tokens: usize) -> String {
+    if tokens >= 1000 {
+        format!("{:.1}k", tokens as f64 / 1000.0)
+    } else {
+        tokens.to_string()
+    }
+}
@@ -0,0 +1,116 @@
+use std::collections::BTreeMap;
+use chrono::Local;
+use serde::{Deserialize, Serialize};
+use crate::app::Model;
+use crate::config_manager::get_config;
+use crate::provider::Usage;
+
+/// 单次请求的用量
+#[derive(Serialize, Deserialize, Clone)]
+pub struct UsageRecord {
+    pub role: String,
+    pub model: String,
+    pub timestamp: String,
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    // 配置了该模型的价格时计算
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cost: Option<f64>,
+}
+
+/// 一组请求的合计
+#[derive(Clone, Copy, Default)]
+pub struct UsageTotals {
+    pub requests: usize,
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    pub cost: Option<f64>,
+}
+
+impl UsageTotals {
+    fn add(&mut self, record: &UsageRecord) {
+        self.requests += 1;
+        self.prompt_tokens += record.prompt_tokens;
+        self.completion_tokens += record.completion_tokens;
+        if let Some(cost) = record.cost {
+            *self.cost.get_or_insert(0.0) += cost;
+        }
+    }
+}
+
+/*
+ * -------- [ 用量记录 ] --------
+ * 由 IO 线程在每次请求结束后记录，随会话一同保存，便于事后分析
+ * - 服务端未上报用量的请求不记录
+ * - 费用按 config.prices 中的单价（每百万 token）计算
+ */
+#[derive(Serialize, Deserialize, Default)]
+pub struct UsageLog {
+    records: Vec<UsageRecord>,
+}
+
+impl UsageLog {
+    pub fn record(&mut self, model: Model, usage: Usage) {
+        if usage.prompt_tokens.is_none() && usage.completion_tokens.is_none() {
+            return
+        }
+        let prompt_tokens = usage.prompt_tokens.unwrap_or(0);
+        let completion_tokens = usage.completion_tokens.unwrap_or(0);
+
+        let config = get_config().read().unwrap();
+        let model_name = config.model_name(&model).to_string();
+        let cost = config.prices.get(&model_name).map(|price| price.cost(prompt_tokens, completion_tokens));
+
+        self.records.push(UsageRecord {
+            role: model.name().to_string(),
+            model: model_name,
+            timestamp: Local::now().format("%Y-%m-%d %H:%M:%S").to_string(),
+            prompt_tokens,
+            completion_tokens,
+            cost,
+        });
+    }
+
+    pub fn totals(&self) -> UsageTotals {
+        let mut totals = UsageTotals::default();
+        self.records.iter().for_each(|r| totals.add(r));
+        totals
+    }
+
+    /// `/usage` 显示的报告：按角色与按模型分别汇总
+    pub fn report(&self) -> String {
+        if self.records.is_empty() {
+            return "本次会话尚无用量记录（服务端未上报用量时不记录）".into()
+        }
+
+        let mut by_role: BTreeMap<usize, (&str, UsageTotals)> = BTreeMap::new();
+        let mut by_model: BTreeMap<&str, UsageTotals> = BTreeMap::new();
+        for record in &self.records {
+            let index = Model::parse(&record.role).map_or(usize::MAX, |m| m.index());
+            by_role.entry(index).or_insert((&record.role, UsageTotals::default())).1.add(record);
+            by_model.entry(&record.model).or_default().add(record);
+        }
+
+        let mut output = String::from("会话用量:");
+        for (role, totals) in by_role.values() {
+            output.push_str(&format!("\n  {:<10} {}", role, format_totals(totals)));
+        }
+        output.push_str("\n按模型:");
+        for (model, totals) in &by_model {
+            output.push_str(&format!("\n  {:<10} {}", model, format_totals(totals)));
+        }
+        output.push_str(&format!("\n合计       {}", format_totals(&self.totals())));
+        output
+    }
+}
+
+fn format_totals(totals: &UsageTotals) -> String {
+    let mut text = format!(
+        "{} 次请求 · 输入 {} · 输出 {}",
+        totals.requests, totals.prompt_tokens, totals.completion_tokens,
+    );
+    if let Some(cost) = totals.cost {
+        text.push_str(&format!(" · ${:.4}", cost));
+    }
+    text
+}
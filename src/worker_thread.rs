@@ -1,11 +1,19 @@
 use std::{fs, thread};
+use std::os::unix::process::ExitStatusExt;
 use std::process::Command;
 use std::sync::mpsc;
+use std::time::Instant;
 use diffy::{apply, Patch};
-use crate::app::{Tool, Call, AppMessage, SystemMessage};
+use crate::app::{Tool, Call, AppMessage, SystemMessage, ToolOutput};
 use crate::io_thread::IOThread;
+use crate::redact::redact;
 use crate::ui::Ui;
 
+// 单次 exec 结果写入上下文的上限，超出时保留首尾、省略中间；
+// read 结果不截断，否则模型看不到中间的行却会据此生成补丁，上下文大小交由压缩处理
+const RESULT_MAX_LINES: usize = 400;
+const RESULT_MAX_BYTES: usize = 64 * 1024;
+
 pub struct WorkerThread {
     ui_to_worker: mpsc::Sender<AppMessage>,
     ui_from_worker: mpsc::Receiver<AppMessage>,
@@ -23,22 +31,17 @@ impl WorkerThread {
         thread::spawn(move || {
             while let Ok(msg) = worker_from_ui.recv() {
                 match msg {
-                    AppMessage::SysMsg(SystemMessage::ExecCommand(cmd)) => {
-                        let result = exec_cmd(&*cmd);
-                        let _ = worker_to_ui.send(AppMessage::SysMsg(SystemMessage::ExecResult(result)));
+                    AppMessage::SysMsg(SystemMessage::ExecCommand(model, cmd)) => {
+                        let output = run_exec(&cmd);
+                        let _ = worker_to_ui.send(AppMessage::SysMsg(SystemMessage::ExecResult(model, output)));
                     }
-                    AppMessage::SysMsg(SystemMessage::Read(filename)) => {
-                        let content = read_file(filename.as_str());
-                        let _ = worker_to_ui.send(AppMessage::SysMsg(SystemMessage::ReadResult(content)));
+                    AppMessage::SysMsg(SystemMessage::Read(model, filename)) => {
+                        let output = run_read(&filename);
+                        let _ = worker_to_ui.send(AppMessage::SysMsg(SystemMessage::ReadResult(model, filename, output)));
                     }
-                    AppMessage::SysMsg(SystemMessage::Diff(file_path, diff)) => {
-                        let result = apply_patch(file_path.as_str(), diff.as_str());
-                        let output = match result {
-                            Ok(_) => format!("Patch 成功应用至 <{}>", file_path),
-                            Err(e) => e
-                        };
-
-                        let _ = worker_to_ui.send(AppMessage::SysMsg(SystemMessage::DiffResult(output)));
+                    AppMessage::SysMsg(SystemMessage::Diff(model, file_path, diff)) => {
+                        let output = run_diff(&file_path, &diff);
+                        let _ = worker_to_ui.send(AppMessage::SysMsg(SystemMessage::DiffResult(model, file_path, output)));
                     }
                     _ => {}
                 }
@@ -50,26 +53,28 @@ impl WorkerThread {
 
     /*
      * -------- [ 工具调用结果处理 ] --------
-     * 从 Worker 线程接收 **工具调用结果**，并做下一步处理
+     * 从 Worker 线程接收 **工具调用结果**，在对话区显示后原样转发给 IO 线程，
+     * 保证用户看到的与写入模型上下文的是同一份内容
      */
     pub fn handle_response(&mut self, ui: &mut Ui, io_thread: &mut IOThread) {
         if let Ok(msg) = self.ui_from_worker.try_recv() {
             match msg {
-                AppMessage::SysMsg(SystemMessage::ExecResult(result)) => {
-                    let result_feedback = format!(
-                        "System: Execute Result:\n{}", result
-                    );
-                    io_thread.send(AppMessage::SysMsg(SystemMessage::ExecResult(result_feedback)));
+                AppMessage::SysMsg(SystemMessage::ExecResult(model, output)) => {
+                    ui.tab_of(model).push_result("exec", output.clone());
+                    io_thread.send(AppMessage::SysMsg(SystemMessage::ExecResult(model, output)));
                 }
 
-                AppMessage::SysMsg(SystemMessage::ReadResult(result)) =>
-                    io_thread.send(AppMessage::SysMsg(SystemMessage::ReadResult(result))),
+                AppMessage::SysMsg(SystemMessage::ReadResult(model, filename, output)) => {
+                    ui.tab_of(model).push_result("read", output.clone());
+                    io_thread.send(AppMessage::SysMsg(SystemMessage::ReadResult(model, filename, output)))
+                }
 
-                AppMessage::SysMsg(SystemMessage::DiffResult(result)) =>
-                    io_thread.send(AppMessage::SysMsg(SystemMessage::DiffResult(result))),
+                AppMessage::SysMsg(SystemMessage::DiffResult(model, file_path, output)) => {
+                    ui.tab_of(model).push_result("diff", output.clone());
+                    io_thread.send(AppMessage::SysMsg(SystemMessage::DiffResult(model, file_path, output)))
+                }
 
-                AppMessage::SysMsg(SystemMessage::SystemLog(log)) =>
-                    ui.history_display.push_str(&format!("\n[ERROR]: {}\n", log)),
+                AppMessage::SysMsg(SystemMessage::SystemLog(log)) => ui.push_error(&log),
                 _ => {}
             }
         }
@@ -77,9 +82,8 @@ impl WorkerThread {
     }
 }
 
-/// 验证文件路径是否在当前工作目录下，防止路径穿越攻击
-/// 返回解析后的安全路径
-fn resolve_safe_path(file_path: &str) -> Result<std::path::PathBuf, String> {
+/// 将相对路径解析为基于当前工作目录的绝对路径，并消去 `.` 与 `..`
+pub fn normalize_path(file_path: &str) -> Result<std::path::PathBuf, String> {
     let base_dir = std::env::current_dir()
         .map_err(|e| format!("无法获取当前工作目录: {}", e))?;
 
@@ -95,6 +99,16 @@ fn resolve_safe_path(file_path: &str) -> Result<std::path::PathBuf, String> {
         }
     }
 
+    Ok(normalized_path)
+}
+
+/// 验证文件路径是否在当前工作目录下，防止路径穿越攻击
+/// 返回解析后的安全路径
+fn resolve_safe_path(file_path: &str) -> Result<std::path::PathBuf, String> {
+    let base_dir = std::env::current_dir()
+        .map_err(|e| format!("无法获取当前工作目录: {}", e))?;
+
+    let normalized_path = normalize_path(file_path)?;
     if !normalized_path.starts_with(&base_dir) {
         return Err(format!("越权访问: {}", file_path));
     }
@@ -141,9 +155,71 @@ pub fn parse_tool_call(msg: String) -> Option<Call> {
     None
 }
 
+/* -------- [ 工具执行 ] -------- */
+pub fn run_exec(cmd: &str) -> ToolOutput {
+    timed(true, || exec_cmd(cmd))
+}
+
+pub fn run_read(filename: &str) -> ToolOutput {
+    timed(false, || match read_file(filename) {
+        Ok(content) => (true, "已读取".into(), content),
+        Err(e) => (false, "失败".into(), e)
+    })
+}
+
+pub fn run_diff(file_path: &str, diff: &str) -> ToolOutput {
+    timed(true, || match apply_patch(file_path, diff) {
+        Ok(_) => (true, "已应用".into(), format!("Patch 成功应用至 <{}>", file_path)),
+        Err(e) => (false, "失败".into(), e)
+    })
+}
+
+/// 计时执行工具，脱敏结果并按需截断；f 返回 (是否成功, 状态摘要, 结果文本)
+fn timed(truncate: bool, f: impl FnOnce() -> (bool, String, String)) -> ToolOutput {
+    let started = Instant::now();
+    let (success, status, content) = f();
+    let content = redact(&content);
+    let (content, truncated) = if truncate { truncate_output(content) } else { (content, None) };
+    ToolOutput { success, status, elapsed: started.elapsed(), content, truncated }
+}
+
+/*
+ * -------- [ 结果截断 ] --------
+ * 超过 RESULT_MAX_LINES 行时保留首尾各一半，中间以标记行代替；
+ * 仍超过 RESULT_MAX_BYTES 时按字节截断。返回截断后的文本与原始行数
+ */
+fn truncate_output(content: String) -> (String, Option<usize>) {
+    let total = content.lines().count();
+    if total <= RESULT_MAX_LINES && content.len() <= RESULT_MAX_BYTES {
+        return (content, None)
+    }
+
+    let mut output = if total > RESULT_MAX_LINES {
+        let lines: Vec<&str> = content.lines().collect();
+        let half = RESULT_MAX_LINES / 2;
+        format!(
+            "{}\n... [输出已截断：共 {} 行，省略中间 {} 行] ...\n{}\n",
+            lines[..half].join("\n"), total, total - RESULT_MAX_LINES, lines[total - half..].join("\n")
+        )
+    } else {
+        content
+    };
+
+    if output.len() > RESULT_MAX_BYTES {
+        let mut end = RESULT_MAX_BYTES;
+        while !output.is_char_boundary(end) {
+            end -= 1;
+        }
+        output.truncate(end);
+        output.push_str(&format!("\n... [输出已截断：超过 {} 字节] ...\n", RESULT_MAX_BYTES));
+    }
+
+    (output, Some(total))
+}
+
 /// 安全地执行命令，避免 shell 注入
 /// 将命令字符串解析为程序名和参数，直接执行而不通过 shell
-fn exec_cmd(cmd: &str) -> String {
+fn exec_cmd(cmd: &str) -> (bool, String, String) {
     let output = Command::new("sh")
         .arg("-c")
         .arg(cmd)
@@ -154,25 +230,30 @@ fn exec_cmd(cmd: &str) -> String {
             let status = &result.status;
             let stdout = String::from_utf8_lossy(&result.stdout).to_string();
             let stderr = String::from_utf8_lossy(&result.stderr).to_string();
-            format!("status: {}\nstdout: {}\nstderr: {}\n", status, stdout, stderr)
+            let summary = match (status.code(), status.signal()) {
+                (Some(code), _) => format!("exit {}", code),
+                (None, Some(signal)) => format!("signal {}", signal),
+                _ => status.to_string(),
+            };
+            let content = format!("System: Execute Result:\nstatus: {}\nstdout: {}\nstderr: {}\n", status, stdout, stderr);
+            (status.success(), summary, content)
         }
         Err(e) => {
-            format!("命令执行失败: {}", e)
+            (false, "失败".into(), format!("System: Execute Result:\n命令执行失败: {}", e))
         }
     }
 }
 
-fn read_file(filename: &str) -> String {
-    let mut output = String::new();
-    let full_content = fs::read_to_string(filename).unwrap();
+fn read_file(filename: &str) -> Result<String, String> {
+    let full_content = fs::read_to_string(filename)
+        .map_err(|e| format!("无法读取文件 <{}>: {}", filename, e))?;
 
-    let mut count = 0;
-    for line in full_content.lines() {
-        count += 1;
-        output.push_str(format!("{}) {}\n", count, line).as_str());
+    let mut output = String::new();
+    for (i, line) in full_content.lines().enumerate() {
+        output.push_str(format!("{}) {}\n", i + 1, line).as_str());
     }
 
-    output
+    Ok(output)
 }
 
 fn apply_patch(file_path: &str, diff: &str) -> Result<(), String> {
@@ -204,4 +285,68 @@ fn apply_patch(file_path: &str, diff: &str) -> Result<(), String> {
         .map_err(|e| format!("无法写入文件 <{}>: {}", safe_path.display(), e))?;
 
     Ok(())
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn numbered(count: usize) -> String {
+        (1..=count).map(|i| format!("{}\n", i)).collect()
+    }
+
+    #[test]
+    fn truncate_output_keeps_output_within_limits() {
+        let content = numbered(RESULT_MAX_LINES);
+        assert_eq!(truncate_output(content.clone()), (content, None));
+
+        let content = "x".repeat(RESULT_MAX_BYTES);
+        assert_eq!(truncate_output(content.clone()), (content, None));
+    }
+
+    #[test]
+    fn truncate_output_keeps_head_and_tail_lines() {
+        let total = RESULT_MAX_LINES + 1;
+        let (output, truncated) = truncate_output(numbered(total));
+        assert_eq!(truncated, Some(total));
+
+        let lines: Vec<&str> = output.lines().collect();
+        let half = RESULT_MAX_LINES / 2;
+        assert_eq!(lines.len(), RESULT_MAX_LINES + 1);
+        assert_eq!(lines[half - 1], half.to_string());
+        assert!(lines[half].contains(&format!("共 {} 行，省略中间 1 行", total)));
+        assert_eq!(lines[half + 1], (total - half + 1).to_string());
+        assert_eq!(*lines.last().unwrap(), total.to_string());
+    }
+
+    #[test]
+    fn truncate_output_cuts_bytes_on_char_boundary() {
+        // 单行超过字节上限，且上限落在多字节字符中间
+        let content = "中".repeat(RESULT_MAX_BYTES / 3 + 1);
+        let (output, truncated) = truncate_output(content);
+        assert_eq!(truncated, Some(1));
+
+        let (kept, marker) = output.split_once('\n').unwrap();
+        assert_eq!(kept.len(), RESULT_MAX_BYTES / 3 * 3);
+        assert!(marker.contains(&format!("超过 {} 字节", RESULT_MAX_BYTES)));
+    }
+
+    #[test]
+    fn read_results_are_never_truncated() {
+        // 行数与字节数都超过上限
+        let line = "x".repeat(99);
+        let path = std::env::temp_dir().join(format!("oxicodent-read-{}.txt", std::process::id()));
+        fs::write(&path, format!("{}\n", line).repeat(RESULT_MAX_LINES * 2)).unwrap();
+        let output = run_read(path.to_str().unwrap());
+        fs::remove_file(&path).unwrap();
+
+        let expected: String = (1..=RESULT_MAX_LINES * 2).map(|i| format!("{}) {}\n", i, line)).collect();
+        assert!(expected.len() > RESULT_MAX_BYTES);
+        assert!(output.success);
+        assert_eq!(output.truncated, None);
+        assert_eq!(output.content, expected);
+
+        // 同样的内容经由 exec 等工具返回时会被截断
+        assert!(truncate_output(expected).1.is_some());
+    }
+}